@@ -0,0 +1,41 @@
+use crate::{
+    light::Light,
+    ray::Ray,
+    vec3::{unit_vector, Color},
+};
+
+pub trait Background: Send + Sync {
+    // Radiance of rays escaping the scene, without what comes from the background's light.
+    fn value(&self, r: Ray) -> Color;
+
+    // A part of the background bright and small enough to be sampled as a light, such as the
+    // sun of a sky. Scenes register it with their other lights.
+    fn light(&self) -> Option<Box<dyn Light>> {
+        None
+    }
+}
+
+pub struct Gradient {
+    pub bottom: Color,
+    pub top: Color,
+}
+
+impl Gradient {
+    pub fn new(bottom: Color, top: Color) -> Self {
+        Self { bottom, top }
+    }
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        Self::new(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0))
+    }
+}
+
+impl Background for Gradient {
+    fn value(&self, r: Ray) -> Color {
+        let unit_direction = unit_vector(r.direction());
+        let t = 0.5 * (unit_direction.y() + 1.0);
+        (1.0 - t) * self.bottom + t * self.top
+    }
+}
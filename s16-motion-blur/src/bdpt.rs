@@ -0,0 +1,615 @@
+use std::sync::Arc;
+
+use crate::{
+    camera::Camera,
+    film::Film,
+    hittable::HitRecord,
+    integrator::power_heuristic,
+    medium_stack::{HitMedia, MediumStack},
+    ray::Ray,
+    scene::Scene,
+    vec3::{dot, unit_vector, Color, Point3, Vec3},
+};
+
+// Bidirectional path tracing after Veach's thesis and pbrt: a camera subpath and a light
+// subpath are connected at every pair of vertices and the strategies are combined with the
+// power heuristic. Strategies that only touch the camera through its lens (t = 1) are
+// splatted to `film` at the pixel they project to.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum VertexKind {
+    Camera,
+    Light,
+    Surface,
+}
+
+struct Vertex {
+    kind: VertexKind,
+    p: Point3,
+    n: Vec3,
+    // Unit direction towards the previous vertex of the subpath.
+    wo: Vec3,
+    rec: Option<HitRecord>,
+    media: HitMedia,
+    light: Option<usize>,
+    beta: Color,
+    pdf_fwd: f64,
+    pdf_rev: f64,
+    delta: bool,
+}
+
+impl Vertex {
+    fn camera(p: Point3, beta: Color) -> Self {
+        Self {
+            kind: VertexKind::Camera,
+            p,
+            n: Vec3::new(0.0, 0.0, 0.0),
+            wo: Vec3::new(0.0, 0.0, 0.0),
+            rec: None,
+            media: HitMedia::default(),
+            light: None,
+            beta,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta: false,
+        }
+    }
+
+    fn light(light: usize, p: Point3, n: Vec3, beta: Color, pdf_fwd: f64) -> Self {
+        Self {
+            kind: VertexKind::Light,
+            p,
+            n,
+            wo: Vec3::new(0.0, 0.0, 0.0),
+            rec: None,
+            media: HitMedia::default(),
+            light: Some(light),
+            beta,
+            pdf_fwd,
+            pdf_rev: 0.0,
+            delta: false,
+        }
+    }
+
+    fn is_on_surface(&self) -> bool {
+        self.n.length_squared() > 0.0
+    }
+
+    fn is_connectible(&self) -> bool {
+        match self.kind {
+            VertexKind::Surface => !self.delta,
+            _ => true,
+        }
+    }
+
+    fn is_delta_light(&self, scene: &Scene) -> bool {
+        self.kind == VertexKind::Light && self.light.is_some_and(|l| scene.lights[l].is_delta())
+    }
+
+    // Converts a solid angle density at this vertex into an area density at `next`.
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        let w = next.p - self.p;
+        let distance_squared = w.length_squared();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        let mut pdf = pdf / distance_squared;
+        if next.is_on_surface() {
+            pdf *= dot(next.n, w / distance_squared.sqrt()).abs();
+        }
+        pdf
+    }
+
+    // BSDF towards `next`, including the cosine at this vertex.
+    fn f(&self, next: &Vertex) -> Color {
+        match &self.rec {
+            Some(rec) => {
+                let wi = unit_vector(next.p - self.p);
+                rec.material.eval(rec, self.wo, wi)
+            }
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn le(&self) -> Color {
+        match &self.rec {
+            Some(rec) if self.light.is_some() => rec.material.emitted(rec),
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    // Area density at `next` of sampling it from this vertex, having arrived from `prev`.
+    fn pdf(&self, scene: &Scene, camera: &Camera, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        if self.kind == VertexKind::Light {
+            return self.pdf_light(scene, next);
+        }
+
+        let wn = next.p - self.p;
+        if wn.length_squared() == 0.0 {
+            return 0.0;
+        }
+        let wn = unit_vector(wn);
+
+        let pdf = match (self.kind, &self.rec, prev) {
+            (VertexKind::Camera, _, _) => camera.pdf_we(Ray::new(self.p, wn)).1,
+            (_, Some(rec), Some(prev)) => {
+                let wp = unit_vector(prev.p - self.p);
+                rec.material.pdf(rec, wp, wn)
+            }
+            _ => 0.0,
+        };
+        self.convert_density(pdf, next)
+    }
+
+    fn pdf_light(&self, scene: &Scene, next: &Vertex) -> f64 {
+        let light = match self.light {
+            Some(l) => &scene.lights[l],
+            None => return 0.0,
+        };
+        let w = next.p - self.p;
+        let distance_squared = w.length_squared();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        let w = w / distance_squared.sqrt();
+
+        let (_, pdf_dir) = light.pdf_le(self.p, self.n, w);
+        let mut pdf = pdf_dir / distance_squared;
+        if next.is_on_surface() {
+            pdf *= dot(next.n, w).abs();
+        }
+        pdf
+    }
+
+    fn pdf_light_origin(&self, scene: &Scene, next: &Vertex) -> f64 {
+        let light_index = match self.light {
+            Some(l) => l,
+            None => return 0.0,
+        };
+        let w = unit_vector(next.p - self.p);
+        let (pdf_pos, _) = scene.lights[light_index].pdf_le(self.p, self.n, w);
+        pdf_pos * scene.emitting_light_pmf(light_index)
+    }
+}
+
+pub fn ray_color_bdpt(
+    r: Ray,
+    scene: &Scene,
+    camera: &Camera,
+    film: &Film,
+    max_depth: i32,
+) -> Color {
+    let max_depth = max_depth.max(0) as usize;
+    let mut camera_path = Vec::with_capacity(max_depth + 2);
+    let mut l = generate_camera_subpath(scene, camera, r, max_depth + 2, &mut camera_path);
+    let mut light_path = Vec::with_capacity(max_depth + 1);
+    generate_light_subpath(scene, max_depth + 1, &mut light_path);
+
+    for t in 1..=camera_path.len() {
+        // s = 1 resamples its light vertex, so it does not need a light subpath.
+        for s in 0..=light_path.len().max(1) {
+            if (s == 1 && t == 1) || s + t < 2 || s + t - 2 > max_depth {
+                continue;
+            }
+            let (contribution, raster) = connect(scene, camera, &light_path, &camera_path, s, t);
+            if t == 1 {
+                if let Some((u, v)) = raster {
+                    film.add_splat(u, v, contribution);
+                }
+            } else {
+                l += contribution;
+            }
+        }
+    }
+
+    l
+}
+
+// Returns the radiance of camera paths that escape to the background, see `escaped_radiance`.
+fn generate_camera_subpath(
+    scene: &Scene,
+    camera: &Camera,
+    r: Ray,
+    max_vertices: usize,
+    path: &mut Vec<Vertex>,
+) -> Color {
+    let beta = Color::new(1.0, 1.0, 1.0);
+    path.push(Vertex::camera(r.origin(), beta));
+    let (_, pdf_dir) = camera.pdf_we(r);
+    if pdf_dir == 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    random_walk(scene, r, beta, pdf_dir, max_vertices - 1, true, path)
+}
+
+fn generate_light_subpath(scene: &Scene, max_vertices: usize, path: &mut Vec<Vertex>) {
+    if max_vertices == 0 {
+        return;
+    }
+    let (light_index, light_pmf) = match scene.sample_emitting_light() {
+        Some(sampled) => sampled,
+        None => return,
+    };
+
+    // Paths from lights at infinity are only built by connecting to them, see `connect`.
+    if scene.lights[light_index].is_infinite() {
+        return;
+    }
+    let es = match scene.lights[light_index].sample_le() {
+        Some(es) => es,
+        None => return,
+    };
+    if es.pdf_pos == 0.0 || es.pdf_dir == 0.0 || es.radiance.length_squared() == 0.0 {
+        return;
+    }
+
+    path.push(Vertex::light(
+        light_index,
+        es.ray.origin(),
+        es.normal,
+        es.radiance,
+        es.pdf_pos * light_pmf,
+    ));
+    let cos_theta = if es.normal.length_squared() > 0.0 {
+        dot(es.normal, unit_vector(es.ray.direction())).abs()
+    } else {
+        1.0
+    };
+    let beta = es.radiance * (cos_theta / (light_pmf * es.pdf_pos * es.pdf_dir));
+    random_walk(
+        scene,
+        es.ray,
+        beta,
+        es.pdf_dir,
+        max_vertices - 1,
+        false,
+        path,
+    );
+}
+
+fn random_walk(
+    scene: &Scene,
+    r: Ray,
+    beta: Color,
+    pdf: f64,
+    max_vertices: usize,
+    from_camera: bool,
+    path: &mut Vec<Vertex>,
+) -> Color {
+    let mut ray = r;
+    let mut beta = beta;
+    let mut pdf_fwd = pdf;
+    let mut bounces = 0;
+    let mut media = MediumStack::new();
+
+    while bounces < max_vertices {
+        let (object, rec, hit_media) = match scene.hit_object(ray, &media, 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => {
+                if from_camera {
+                    return beta * escaped_radiance(scene, &ray, &path[path.len() - 1], pdf_fwd);
+                }
+                break;
+            }
+        };
+
+        let material = Arc::clone(&rec.material);
+        let wo = -unit_vector(ray.direction());
+        beta = beta * rec.transmittance;
+        let mut vertex = Vertex {
+            kind: VertexKind::Surface,
+            p: rec.p,
+            n: rec.normal,
+            wo,
+            rec: None,
+            media: hit_media,
+            light: if material.is_emissive() {
+                scene.area_light(object)
+            } else {
+                None
+            },
+            beta,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta: false,
+        };
+        vertex.pdf_fwd = path[path.len() - 1].convert_density(pdf_fwd, &vertex);
+
+        let mut scattered = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        let mut attenuation = Color::new(0.0, 0.0, 0.0);
+        let scatters = material.scatter(ray, &rec, &mut attenuation, &mut scattered);
+        vertex.rec = Some(rec);
+        path.push(vertex);
+        bounces += 1;
+        if bounces >= max_vertices || !scatters {
+            break;
+        }
+
+        let current = path.len() - 1;
+        let wi = unit_vector(scattered.direction());
+        let pdf_rev = if material.is_specular() {
+            path[current].delta = true;
+            pdf_fwd = 0.0;
+            0.0
+        } else {
+            let rec = path[current].rec.as_ref().unwrap();
+            pdf_fwd = material.pdf(rec, wo, wi);
+            material.pdf(rec, wi, wo)
+        };
+
+        beta = beta * attenuation;
+        let rev = path[current].convert_density(pdf_rev, &path[current - 1]);
+        path[current - 1].pdf_rev = rev;
+        ray = scattered;
+        media = *hit_media.leaving(path[current].n, scattered.direction());
+    }
+
+    Color::new(0.0, 0.0, 0.0)
+}
+
+// Radiance reaching a camera subpath that escapes along `r` from its last vertex `v`, which
+// sampled `r` with solid angle density `pdf`. Only the unidirectional strategy reaches the
+// background. Lights at infinity can also be connected to from `v` (s = 1), and the two
+// strategies are weighted against each other.
+fn escaped_radiance(scene: &Scene, r: &Ray, v: &Vertex, pdf: f64) -> Color {
+    let mut l = scene.background.value(*r);
+    let wi = unit_vector(r.direction());
+    for &light in scene.infinite_lights() {
+        let le = scene.lights[light].le(r);
+        if v.kind == VertexKind::Camera || v.delta {
+            l += le;
+        } else if le.length_squared() > 0.0 {
+            let light_pdf = scene.emitting_light_pmf(light) * scene.lights[light].pdf_li(v.p, wi);
+            l += le * power_heuristic(pdf, light_pdf);
+        }
+    }
+    l
+}
+
+fn connect(
+    scene: &Scene,
+    camera: &Camera,
+    light_path: &[Vertex],
+    camera_path: &[Vertex],
+    s: usize,
+    t: usize,
+) -> (Color, Option<(f64, f64)>) {
+    let black = (Color::new(0.0, 0.0, 0.0), None);
+    let mut sampled = None;
+    let mut raster = None;
+
+    let l = if s == 0 {
+        let pt = &camera_path[t - 1];
+        pt.le() * pt.beta
+    } else if t == 1 {
+        let qs = &light_path[s - 1];
+        if !qs.is_connectible() {
+            return black;
+        }
+        let cs = match camera.sample_wi(qs.p) {
+            Some(cs) if cs.pdf > 0.0 && cs.importance.length_squared() > 0.0 => cs,
+            _ => return black,
+        };
+        let v = Vertex::camera(cs.p_lens, cs.importance / cs.pdf);
+        let mut l = qs.beta * qs.f(&v) * v.beta;
+        if l.length_squared() > 0.0 {
+            l = l
+                * interior_transmittance(qs, cs.wi, cs.distance)
+                * scene.segment_transmittance(qs.p, cs.wi, cs.distance);
+        }
+        if l.length_squared() == 0.0 {
+            return black;
+        }
+        raster = Some((cs.s, cs.t));
+        sampled = Some(v);
+        l
+    } else if s == 1 {
+        let pt = &camera_path[t - 1];
+        if !pt.is_connectible() {
+            return black;
+        }
+        let (light_index, light_pmf) = match scene.sample_emitting_light() {
+            Some(sampled) => sampled,
+            None => return black,
+        };
+        let light = &scene.lights[light_index];
+
+        // Light subpaths never start at infinity, so the connection only competes with camera
+        // paths escaping towards the light, see `escaped_radiance`.
+        if light.is_infinite() {
+            let ls = match light.sample_li(pt.p) {
+                Some(ls) if ls.pdf > 0.0 => ls,
+                _ => return black,
+            };
+            let v = Vertex::light(
+                light_index,
+                pt.p + ls.wi,
+                Vec3::new(0.0, 0.0, 0.0),
+                ls.radiance,
+                0.0,
+            );
+            let mut l = pt.beta * pt.f(&v) * ls.radiance / (light_pmf * ls.pdf);
+            if l.length_squared() > 0.0 {
+                l = l
+                    * interior_transmittance(pt, ls.wi, ls.distance)
+                    * scene.segment_transmittance(pt.p, ls.wi, ls.distance);
+            }
+            if l.length_squared() == 0.0 {
+                return black;
+            }
+            let weight = match &pt.rec {
+                Some(rec) if !light.is_delta() => {
+                    power_heuristic(light_pmf * ls.pdf, rec.material.pdf(rec, pt.wo, ls.wi))
+                }
+                _ => 1.0,
+            };
+            return (l * weight, None);
+        }
+
+        let es = match light.sample_le() {
+            Some(es) if es.pdf_pos > 0.0 => es,
+            _ => return black,
+        };
+
+        let p = es.ray.origin();
+        let w = pt.p - p;
+        let distance = w.length();
+        if distance == 0.0 {
+            return black;
+        }
+        let w = w / distance;
+        let pdf_area = es.pdf_pos * light_pmf;
+        let cos_light = if es.normal.length_squared() > 0.0 {
+            dot(es.normal, w).abs()
+        } else {
+            1.0
+        };
+
+        let v = Vertex::light(
+            light_index,
+            p,
+            es.normal,
+            light.l(p, es.normal, w) / pdf_area,
+            pdf_area,
+        );
+        let mut l = pt.beta * pt.f(&v) * v.beta * (cos_light / (distance * distance));
+        if l.length_squared() > 0.0 {
+            l = l
+                * interior_transmittance(pt, -w, distance)
+                * scene.segment_transmittance(pt.p, -w, distance);
+        }
+        if l.length_squared() == 0.0 {
+            return black;
+        }
+        sampled = Some(v);
+        l
+    } else {
+        let qs = &light_path[s - 1];
+        let pt = &camera_path[t - 1];
+        if !qs.is_connectible() || !pt.is_connectible() {
+            return black;
+        }
+        let d = pt.p - qs.p;
+        let distance = d.length();
+        if distance == 0.0 {
+            return black;
+        }
+        let mut l = qs.beta * qs.f(pt) * pt.f(qs) * pt.beta / (distance * distance);
+        if l.length_squared() > 0.0 {
+            l = l
+                * interior_transmittance(qs, d / distance, distance)
+                * scene.segment_transmittance(qs.p, d / distance, distance);
+        }
+        if l.length_squared() == 0.0 {
+            return black;
+        }
+        l
+    };
+
+    if l.length_squared() == 0.0 {
+        return black;
+    }
+    let weight = mis_weight(
+        scene,
+        camera,
+        light_path,
+        camera_path,
+        sampled.as_ref(),
+        s,
+        t,
+    );
+    (l * weight, raster)
+}
+
+// Absorption along a connection leaving `v` in the unit direction `w`.
+fn interior_transmittance(v: &Vertex, w: Vec3, distance: f64) -> Color {
+    v.rec.as_ref().map_or(Color::new(1.0, 1.0, 1.0), |rec| {
+        v.media.leaving(rec.normal, w).transmittance(distance)
+    })
+}
+
+fn mis_weight(
+    scene: &Scene,
+    camera: &Camera,
+    light_path: &[Vertex],
+    camera_path: &[Vertex],
+    sampled: Option<&Vertex>,
+    s: usize,
+    t: usize,
+) -> f64 {
+    if s + t == 2 {
+        return 1.0;
+    }
+
+    let mut cam: Vec<&Vertex> = camera_path[..t].iter().collect();
+    let lig: Vec<&Vertex> = match sampled {
+        Some(v) if s == 1 => vec![v],
+        _ => light_path[..s].iter().collect(),
+    };
+    if let Some(v) = sampled {
+        if t == 1 {
+            cam[0] = v;
+        }
+    }
+
+    let cam_fwd: Vec<f64> = cam.iter().map(|v| v.pdf_fwd).collect();
+    let mut cam_rev: Vec<f64> = cam.iter().map(|v| v.pdf_rev).collect();
+    let mut cam_delta: Vec<bool> = cam.iter().map(|v| v.delta).collect();
+    let light_fwd: Vec<f64> = lig.iter().map(|v| v.pdf_fwd).collect();
+    let mut light_rev: Vec<f64> = lig.iter().map(|v| v.pdf_rev).collect();
+    let mut light_delta: Vec<bool> = lig.iter().map(|v| v.delta).collect();
+
+    let pt = cam[t - 1];
+    let pt_minus = if t > 1 { Some(cam[t - 2]) } else { None };
+    let qs = if s > 0 { Some(lig[s - 1]) } else { None };
+    let qs_minus = if s > 1 { Some(lig[s - 2]) } else { None };
+
+    // The connection vertices are no longer degenerate, and their reverse densities are
+    // those of the strategy that would have sampled them from the other subpath.
+    cam_delta[t - 1] = false;
+    if s > 0 {
+        light_delta[s - 1] = false;
+    }
+    cam_rev[t - 1] = match qs {
+        Some(qs) => qs.pdf(scene, camera, qs_minus, pt),
+        None => pt_minus.map_or(0.0, |m| pt.pdf_light_origin(scene, m)),
+    };
+    if let Some(pt_minus) = pt_minus {
+        cam_rev[t - 2] = match qs {
+            Some(qs) => pt.pdf(scene, camera, Some(qs), pt_minus),
+            None => pt.pdf_light(scene, pt_minus),
+        };
+    }
+    if let Some(qs) = qs {
+        light_rev[s - 1] = pt.pdf(scene, camera, pt_minus, qs);
+        if let Some(qs_minus) = qs_minus {
+            light_rev[s - 2] = qs.pdf(scene, camera, Some(pt), qs_minus);
+        }
+    }
+
+    let remap0 = |f: f64| if f != 0.0 { f } else { 1.0 };
+    let mut sum_ri = 0.0;
+
+    let mut ri = 1.0;
+    for i in (1..t).rev() {
+        ri *= remap0(cam_rev[i]) / remap0(cam_fwd[i]);
+        if !cam_delta[i] && !cam_delta[i - 1] {
+            sum_ri += ri;
+        }
+    }
+
+    let mut ri = 1.0;
+    for i in (0..s).rev() {
+        ri *= remap0(light_rev[i]) / remap0(light_fwd[i]);
+        let delta_light_vertex = if i > 0 {
+            light_delta[i - 1]
+        } else {
+            lig[0].is_delta_light(scene)
+        };
+        if !light_delta[i] && !delta_light_vertex {
+            sum_ri += ri;
+        }
+    }
+
+    1.0 / (1.0 + sum_ri)
+}
@@ -0,0 +1,212 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Material, Shape},
+    medium_stack::Interior,
+    ray::Ray,
+    texture::Texture,
+    vec3::{cross, dot, unit_vector, Color, Point3, Vec3},
+};
+
+// Shading normals closer to the surface than this are bent back towards the geometric normal.
+const MIN_COS: f64 = 0.01;
+
+// Offset in surface coordinates for the finite differences of height maps.
+const DELTA: f64 = 0.0005;
+
+enum Perturbation {
+    // Tangent-space normals with x along dpdu, y along dpdv and z out of the surface, encoded
+    // as 0.5 * n + 0.5.
+    NormalMap(Arc<dyn Texture>),
+    // Displacement along the normal, the first channel times the scale.
+    Height(Arc<dyn Texture>, f64),
+}
+
+// Perturbs the shading normals of a shape to add detail its geometry does not have. Light
+// transport still follows the geometric surface: the scene wraps the materials of bumped
+// objects in a `LeakGuard`.
+pub struct Bumped {
+    shape: Arc<dyn Shape>,
+    perturbation: Perturbation,
+}
+
+impl Bumped {
+    // Normal maps are linear data, so images for them should be read with `load_linear`.
+    pub fn normal_map<S: 'static + Shape, T: 'static + Texture>(shape: S, map: T) -> Self {
+        Self {
+            shape: Arc::new(shape),
+            perturbation: Perturbation::NormalMap(Arc::new(map)),
+        }
+    }
+
+    // Bump mapping (Blinn) with heights `scale` times the first channel of `height`.
+    pub fn height<S: 'static + Shape, T: 'static + Texture>(
+        shape: S,
+        height: T,
+        scale: f64,
+    ) -> Self {
+        Self {
+            shape: Arc::new(shape),
+            perturbation: Perturbation::Height(Arc::new(height), scale),
+        }
+    }
+
+    // The perturbed normal on the outside of the surface.
+    fn shading_normal(&self, rec: &HitRecord, n: Vec3) -> Option<Vec3> {
+        let (dpdu, dpdv) = (rec.dpdu, rec.dpdv);
+        match &self.perturbation {
+            Perturbation::NormalMap(map) => {
+                let tangent = dpdu - dot(dpdu, n) * n;
+                if tangent.length_squared() == 0.0 {
+                    return None;
+                }
+                let tangent = unit_vector(tangent);
+                let bitangent = cross(n, tangent);
+                let bitangent = if dot(bitangent, dpdv) < 0.0 {
+                    -bitangent
+                } else {
+                    bitangent
+                };
+                let c = 2.0 * map.value(rec.u, rec.v, rec.p) - Color::new(1.0, 1.0, 1.0);
+                Some(c.x() * tangent + c.y() * bitangent + c.z() * n)
+            }
+            Perturbation::Height(height, scale) => {
+                let h = |u: f64, v: f64, p: Point3| scale * height.value(u, v, p).x();
+                let h0 = h(rec.u, rec.v, rec.p);
+                let hu = h(rec.u + DELTA, rec.v, rec.p + DELTA * dpdu);
+                let hv = h(rec.u, rec.v + DELTA, rec.p + DELTA * dpdv);
+                let dpdu = dpdu + (hu - h0) / DELTA * n;
+                let dpdv = dpdv + (hv - h0) / DELTA * n;
+                let perturbed = cross(dpdu, dpdv);
+                if dot(perturbed, n) < 0.0 {
+                    Some(-perturbed)
+                } else {
+                    Some(perturbed)
+                }
+            }
+        }
+    }
+}
+
+impl Shape for Bumped {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if !self.shape.hit(r, t_min, t_max, rec) {
+            return false;
+        }
+        let outward = if rec.front_face {
+            rec.geometric_normal
+        } else {
+            -rec.geometric_normal
+        };
+        let shading = match self.shading_normal(rec, outward) {
+            Some(n) if n.length_squared() > 0.0 => unit_vector(n),
+            _ => return true,
+        };
+
+        // Normals facing away from the surface would shade it as seen from behind.
+        let cos = dot(shading, outward);
+        let shading = if cos < MIN_COS {
+            unit_vector(shading + (MIN_COS - cos) * outward)
+        } else {
+            shading
+        };
+        rec.normal = if rec.front_face { shading } else { -shading };
+        rec.dpdu = rec.dpdu - dot(rec.dpdu, shading) * shading;
+        rec.dpdv = rec.dpdv - dot(rec.dpdv, shading) * shading;
+        true
+    }
+
+    fn occluded(&self, r: &Ray, t_max: f64) -> bool {
+        self.shape.occluded(r, t_max)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.shape.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        self.shape.random(origin)
+    }
+
+    fn area(&self) -> f64 {
+        self.shape.area()
+    }
+
+    fn sample_area(&self) -> Option<(Point3, Vec3)> {
+        self.shape.sample_area()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.shape.bounding_box()
+    }
+
+    fn perturbs_normals(&self) -> bool {
+        true
+    }
+}
+
+// Restricts a material at a hit with a perturbed shading normal to directions on which the
+// shading and the geometric normal agree, so that surfaces stay opaque from both sides. Where
+// they are the same it changes nothing.
+pub struct LeakGuard {
+    material: Arc<dyn Material>,
+}
+
+impl LeakGuard {
+    pub fn new(material: Arc<dyn Material>) -> Self {
+        Self { material }
+    }
+}
+
+impl Material for LeakGuard {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        self.material.scatter(r_in, rec, attenuation, scattered)
+            && rec.is_consistent(scattered.direction())
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.material.emitted(rec)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        if !rec.is_consistent(wi) {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.material.eval(rec, wo, wi)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        if !rec.is_consistent(wi) {
+            return 0.0;
+        }
+        self.material.pdf(rec, wo, wi)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.material.is_specular()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.material.is_dispersive()
+    }
+
+    fn interior(&self, wavelength: Option<f64>) -> Option<Interior> {
+        self.material.interior(wavelength)
+    }
+
+    fn choose(&self, rec: &HitRecord) -> Option<Arc<dyn Material>> {
+        let material = self.material.choose(rec)?;
+        Some(Arc::new(LeakGuard::new(material)))
+    }
+}
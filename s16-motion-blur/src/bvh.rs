@@ -0,0 +1,205 @@
+use crate::{aabb::Aabb, ray::Ray};
+
+const MAX_LEAF_SIZE: usize = 4;
+const N_BUCKETS: usize = 12;
+// Cost of visiting an interior node relative to intersecting one item.
+const TRAVERSAL_COST: f64 = 0.125;
+// Deeper nodes are split at the median, which keeps the depth of the tree within this plus
+// log2 of its size, and so the traversal stack within `STACK_SIZE`.
+const MAX_SAH_DEPTH: usize = 32;
+const STACK_SIZE: usize = 64;
+
+struct BvhNode {
+    bounds: Aabb,
+    // The first item of a leaf, or the second child of an interior node. The first child of an
+    // interior node directly follows it.
+    index: usize,
+    // Number of items in a leaf, zero for interior nodes.
+    count: usize,
+    // Axis interior nodes are split along.
+    axis: usize,
+}
+
+// Bounding volume hierarchy over items identified by index, split by the surface area
+// heuristic.
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    // Item indices, each leaf's a contiguous run.
+    items: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn build(mut items: Vec<(usize, Aabb)>) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::new(),
+            items: Vec::with_capacity(items.len()),
+        };
+        if !items.is_empty() {
+            bvh.build_recursive(&mut items, 0);
+        }
+        bvh
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        match self.nodes.first() {
+            Some(root) => root.bounds,
+            None => Aabb::empty(),
+        }
+    }
+
+    fn build_recursive(&mut self, items: &mut [(usize, Aabb)], depth: usize) -> usize {
+        let node_index = self.nodes.len();
+        let mut bounds = Aabb::empty();
+        let mut centroid_bounds = Aabb::empty();
+        for (_, b) in items.iter() {
+            bounds = bounds.union(b);
+            centroid_bounds = centroid_bounds.union_point(b.centroid());
+        }
+
+        let mid = if items.len() > 1 && depth < MAX_SAH_DEPTH {
+            split_by_cost(items, &bounds, &centroid_bounds)
+        } else {
+            None
+        };
+        let mid = match mid {
+            Some(mid) => Some(mid),
+            None if items.len() > MAX_LEAF_SIZE => {
+                let axis = centroid_bounds.longest_axis();
+                let mid = items.len() / 2;
+                items.select_nth_unstable_by(mid, |a, b| {
+                    a.1.centroid()[axis].total_cmp(&b.1.centroid()[axis])
+                });
+                Some(mid)
+            }
+            None => None,
+        };
+
+        let mid = match mid {
+            Some(mid) => mid,
+            None => {
+                self.nodes.push(BvhNode {
+                    bounds,
+                    index: self.items.len(),
+                    count: items.len(),
+                    axis: 0,
+                });
+                self.items.extend(items.iter().map(|(i, _)| *i));
+                return node_index;
+            }
+        };
+
+        self.nodes.push(BvhNode {
+            bounds,
+            index: 0,
+            count: 0,
+            axis: centroid_bounds.longest_axis(),
+        });
+        let (first, second) = items.split_at_mut(mid);
+        self.build_recursive(first, depth + 1);
+        self.nodes[node_index].index = self.build_recursive(second, depth + 1);
+        node_index
+    }
+
+    // Calls `visit` for the items whose boxes `r` passes through between `t_min` and `t_max`,
+    // nearer subtrees first. `visit` may lower the far limit for the rest of the traversal,
+    // after a hit, and ends it by returning false.
+    pub fn traverse<F: FnMut(usize, &mut f64) -> bool>(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        mut visit: F,
+    ) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut t_max = t_max;
+        let mut stack = [0; STACK_SIZE];
+        let mut len = 1;
+        while len > 0 {
+            len -= 1;
+            let node_index = stack[len];
+            let node = &self.nodes[node_index];
+            if !node.bounds.hit(r, t_min, t_max) {
+                continue;
+            }
+            if node.count > 0 {
+                for &item in &self.items[node.index..node.index + node.count] {
+                    if !visit(item, &mut t_max) {
+                        return;
+                    }
+                }
+            } else {
+                // The nearer child goes on top.
+                let (near, far) = if r.direction()[node.axis] < 0.0 {
+                    (node.index, node_index + 1)
+                } else {
+                    (node_index + 1, node.index)
+                };
+                stack[len] = far;
+                stack[len + 1] = near;
+                len += 2;
+            }
+        }
+    }
+}
+
+// Partitions `items` at the cheapest of the bucket boundaries along the longest axis of their
+// centroids. Returns None if a leaf is cheaper, or no split leaves items on both sides.
+fn split_by_cost(
+    items: &mut [(usize, Aabb)],
+    bounds: &Aabb,
+    centroid_bounds: &Aabb,
+) -> Option<usize> {
+    let axis = centroid_bounds.longest_axis();
+    if centroid_bounds.max[axis] == centroid_bounds.min[axis] {
+        return None;
+    }
+    let bucket_of = |b: &Aabb| {
+        let i = (N_BUCKETS as f64 * centroid_bounds.offset(b.centroid())[axis]) as usize;
+        i.min(N_BUCKETS - 1)
+    };
+
+    let mut buckets = [(0, Aabb::empty()); N_BUCKETS];
+    for (_, b) in items.iter() {
+        let bucket = &mut buckets[bucket_of(b)];
+        bucket.0 += 1;
+        bucket.1 = bucket.1.union(b);
+    }
+
+    let area = bounds.surface_area();
+    let mut best: Option<(f64, usize)> = None;
+    for split in 0..N_BUCKETS - 1 {
+        let side = |buckets: &[(usize, Aabb)]| {
+            buckets
+                .iter()
+                .fold((0, Aabb::empty()), |(n, acc), (count, b)| {
+                    (n + count, acc.union(b))
+                })
+        };
+        let (n_below, below) = side(&buckets[..=split]);
+        let (n_above, above) = side(&buckets[split + 1..]);
+        if n_below == 0 || n_above == 0 {
+            continue;
+        }
+        let cost = TRAVERSAL_COST
+            + (n_below as f64 * below.surface_area() + n_above as f64 * above.surface_area())
+                / area;
+        if best.is_none_or(|(c, _)| cost < c) {
+            best = Some((cost, split));
+        }
+    }
+
+    let (cost, split) = best?;
+    if cost >= items.len() as f64 && items.len() <= MAX_LEAF_SIZE {
+        return None;
+    }
+    let mut mid = 0;
+    for i in 0..items.len() {
+        if bucket_of(&items[i].1) <= split {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+    Some(mid)
+}
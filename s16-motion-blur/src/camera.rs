@@ -1,5 +1,17 @@
+use std::f64::consts::PI;
+
 use crate::ray::Ray;
-use crate::vec3::{cross, random_in_unit_disk, unit_vector, Point3, Vec3};
+use crate::vec3::{cross, dot, random_in_unit_disk, unit_vector, Color, Point3, Vec3};
+
+pub struct CameraSample {
+    pub wi: Vec3,
+    pub distance: f64,
+    pub importance: Color,
+    pub pdf: f64,
+    pub p_lens: Point3,
+    pub s: f64,
+    pub t: f64,
+}
 
 #[allow(dead_code)]
 pub struct Camera {
@@ -11,6 +23,8 @@ pub struct Camera {
     u: Vec3,
     v: Vec3,
     w: Vec3,
+    focus_dist: f64,
+    viewport_area: f64,
 }
 
 impl Camera {
@@ -39,6 +53,8 @@ impl Camera {
             u,
             v,
             w,
+            focus_dist,
+            viewport_area: viewport_width * viewport_height,
         }
     }
 
@@ -51,4 +67,76 @@ impl Camera {
             self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
         )
     }
+
+    // Importance emitted along `r` (Veach's We), normalized over the image plane at unit
+    // distance and the lens, together with the lens and direction densities of `get_ray`.
+    pub fn we(&self, r: Ray) -> Color {
+        let cos_theta = dot(unit_vector(r.direction()), -self.w);
+        if cos_theta <= 0.0 || self.raster(r).is_none() {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let weight = 1.0 / (self.viewport_area * self.lens_area() * cos_theta.powi(4));
+        Color::new(weight, weight, weight)
+    }
+
+    pub fn pdf_we(&self, r: Ray) -> (f64, f64) {
+        let cos_theta = dot(unit_vector(r.direction()), -self.w);
+        if cos_theta <= 0.0 || self.raster(r).is_none() {
+            return (0.0, 0.0);
+        }
+        (1.0 / self.lens_area(), 1.0 / (self.viewport_area * cos_theta.powi(3)))
+    }
+
+    // Connects `p_ref` to a point on the lens; `s` and `t` are the `get_ray` coordinates.
+    pub fn sample_wi(&self, p_ref: Point3) -> Option<CameraSample> {
+        let rd = self.lens_radius * random_in_unit_disk();
+        let p_lens = self.origin + self.u * rd.x() + self.v * rd.y();
+        let to_lens = p_lens - p_ref;
+        let distance = to_lens.length();
+        if distance == 0.0 {
+            return None;
+        }
+        let wi = to_lens / distance;
+
+        let r = Ray::new(p_lens, -wi);
+        let (s, t) = self.raster(r)?;
+        let cos_theta = dot(-wi, -self.w);
+        Some(CameraSample {
+            wi,
+            distance,
+            importance: self.we(r),
+            pdf: distance * distance / (cos_theta * self.lens_area()),
+            p_lens,
+            s,
+            t,
+        })
+    }
+
+    // Inverse of `get_ray`: where a ray leaving the lens crosses the plane of focus.
+    pub fn raster(&self, r: Ray) -> Option<(f64, f64)> {
+        let denom = dot(r.direction(), -self.w);
+        if denom <= 0.0 {
+            return None;
+        }
+        let focus_point = self.origin - self.focus_dist * self.w;
+        let t_hit = dot(focus_point - r.origin(), -self.w) / denom;
+        let q = r.at(t_hit) - self.lower_left_corner;
+
+        let s = dot(q, self.horizontal) / self.horizontal.length_squared();
+        let t = dot(q, self.vertical) / self.vertical.length_squared();
+        if (0.0..=1.0).contains(&s) && (0.0..=1.0).contains(&t) {
+            Some((s, t))
+        } else {
+            None
+        }
+    }
+
+    // A pinhole is treated as a lens of unit area so that its positional density is one.
+    fn lens_area(&self) -> f64 {
+        if self.lens_radius > 0.0 {
+            PI * self.lens_radius * self.lens_radius
+        } else {
+            1.0
+        }
+    }
 }
\ No newline at end of file
@@ -1,4 +1,7 @@
-use crate::{util::clamp, vec3::Color};
+use crate::{
+    util::clamp,
+    vec3::{Color, Vec3},
+};
 
 pub fn write_color(pixel_color: Color, samples_per_pixel: i32, pixel: &mut [u8; 3]) {
     let mut r = pixel_color.x();
@@ -18,3 +21,11 @@ pub fn write_color(pixel_color: Color, samples_per_pixel: i32, pixel: &mut [u8;
     pixel[1] = ig;
     pixel[2] = ib;
 }
+
+pub fn xyz_to_linear_srgb(xyz: Vec3) -> Color {
+    Color::new(
+        3.2406 * xyz.x() - 1.5372 * xyz.y() - 0.4986 * xyz.z(),
+        -0.9689 * xyz.x() + 1.8758 * xyz.y() + 0.0415 * xyz.z(),
+        0.0557 * xyz.x() - 0.2040 * xyz.y() + 1.0570 * xyz.z(),
+    )
+}
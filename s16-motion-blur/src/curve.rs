@@ -0,0 +1,388 @@
+use crate::{
+    aabb::Aabb,
+    bvh::Bvh,
+    hittable::{HitRecord, Shape, SHADOW_EPSILON},
+    onb::Onb,
+    ray::Ray,
+    vec3::{cross, dot, unit_vector, Point3, Vec3},
+};
+
+// Deepest subdivision of a curve when intersecting it.
+const MAX_DEPTH: i32 = 10;
+
+#[derive(Debug, Clone, Copy)]
+pub enum CurveKind {
+    // A flat strip that always faces the ray, for fibres too thin to show their shape.
+    Flat,
+    // Faces the ray like `Flat`, with normals curving away across its width as on a round tube.
+    Tube,
+    // A strip turned to face along normals given at either end, interpolated along the curve.
+    Ribbon(Vec3, Vec3),
+}
+
+// A cubic Bezier curve with control points `cp`, swept into a thin strip whose width varies
+// linearly along it. Surface coordinates run along the curve in u, over `u_range`, and across
+// it in v, from 0 to 1. Rays are intersected by subdividing the curve until its pieces are
+// nearly straight (Nakamaru and Ohno, as in pbrt).
+#[derive(Debug, Clone)]
+pub struct Curve {
+    cp: [Point3; 4],
+    width: (f64, f64),
+    kind: CurveKind,
+    u_range: (f64, f64),
+}
+
+// An intersection in the frame of the ray.
+struct CurveHit {
+    t: f64,
+    // Curve parameter, from 0 to 1 over this segment.
+    s: f64,
+    v: f64,
+    width: f64,
+}
+
+impl Curve {
+    pub fn new(cp: [Point3; 4], width0: f64, width1: f64, kind: CurveKind) -> Self {
+        let kind = match kind {
+            CurveKind::Ribbon(n0, n1) => CurveKind::Ribbon(unit_vector(n0), unit_vector(n1)),
+            kind => kind,
+        };
+        Self {
+            cp,
+            width: (width0, width1),
+            kind,
+            u_range: (0.0, 1.0),
+        }
+    }
+
+    pub fn with_u_range(mut self, u0: f64, u1: f64) -> Self {
+        self.u_range = (u0, u1);
+        self
+    }
+
+    // Segments of a smooth curve through `points` (Catmull-Rom), for strands of hair or fur.
+    // u runs from 0 to 1 along the whole strand, and widths and ribbon normals are
+    // interpolated along it.
+    pub fn strand(points: &[Point3], width0: f64, width1: f64, kind: CurveKind) -> Vec<Curve> {
+        let n = points.len();
+        if n < 2 {
+            return Vec::new();
+        }
+        let at = |i: usize| i as f64 / (n - 1) as f64;
+        let lerp = |s: f64, a: f64, b: f64| (1.0 - s) * a + s * b;
+        (0..n - 1)
+            .map(|i| {
+                let (p1, p2) = (points[i], points[i + 1]);
+                // The ends continue straight on.
+                let p0 = if i > 0 { points[i - 1] } else { 2.0 * p1 - p2 };
+                let p3 = if i + 2 < n {
+                    points[i + 2]
+                } else {
+                    2.0 * p2 - p1
+                };
+                let cp = [p1, p1 + (p2 - p0) / 6.0, p2 - (p3 - p1) / 6.0, p2];
+                let (u0, u1) = (at(i), at(i + 1));
+                let kind = match kind {
+                    CurveKind::Ribbon(n0, n1) => {
+                        CurveKind::Ribbon(slerp(u0, n0, n1), slerp(u1, n0, n1))
+                    }
+                    kind => kind,
+                };
+                Curve::new(cp, lerp(u0, width0, width1), lerp(u1, width0, width1), kind)
+                    .with_u_range(u0, u1)
+            })
+            .collect()
+    }
+
+    fn width_at(&self, s: f64) -> f64 {
+        (1.0 - s) * self.width.0 + s * self.width.1
+    }
+
+    fn max_width(&self) -> f64 {
+        self.width.0.max(self.width.1)
+    }
+
+    // The nearest intersection with `r` in (t_min, t_max), or with `any` set the first found.
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64, any: bool) -> Option<CurveHit> {
+        // In the frame of the ray it starts at the origin and runs along z.
+        let frame = Onb::build_from_w(r.direction());
+        let cp = self.cp.map(|p| frame.to_local(p - r.origin()));
+        let length = r.direction().length();
+
+        // Subdivide until the pieces are within a twentieth of the width of a straight line.
+        let mut l0: f64 = 0.0;
+        for i in 0..2 {
+            let d = cp[i] - 2.0 * cp[i + 1] + cp[i + 2];
+            l0 = l0.max(d.x().abs()).max(d.y().abs()).max(d.z().abs());
+        }
+        let eps = 0.05 * self.max_width();
+        let depth = if eps > 0.0 {
+            ((2.0_f64.sqrt() * 6.0 * l0 / (8.0 * eps)).log2() / 2.0).round() as i32
+        } else {
+            MAX_DEPTH
+        };
+
+        let mut query = Query {
+            curve: self,
+            r,
+            length,
+            z_min: t_min * length,
+            z_max: t_max * length,
+            any,
+            hit: None,
+        };
+        query.recurse(&cp, 0.0, 1.0, depth.clamp(0, MAX_DEPTH));
+        query.hit
+    }
+
+    // Position and derivative along the curve at parameter `s`.
+    fn eval(&self, s: f64) -> (Point3, Vec3) {
+        eval_bezier(&self.cp, s)
+    }
+}
+
+// State of one intersection query while subdividing.
+struct Query<'a> {
+    curve: &'a Curve,
+    r: &'a Ray,
+    length: f64,
+    // Range of depths along the ray in which hits count.
+    z_min: f64,
+    z_max: f64,
+    any: bool,
+    hit: Option<CurveHit>,
+}
+
+impl Query<'_> {
+    // Intersects the piece with ray frame control points `cp` between curve parameters `s0`
+    // and `s1`. Returns whether the query is done.
+    fn recurse(&mut self, cp: &[Point3; 4], s0: f64, s1: f64, depth: i32) -> bool {
+        if depth > 0 {
+            let split = split_bezier(cp);
+            let s_mid = 0.5 * (s0 + s1);
+            let halves = [
+                ([split[0], split[1], split[2], split[3]], s0, s_mid),
+                ([split[3], split[4], split[5], split[6]], s_mid, s1),
+            ];
+            for (cp, s0, s1) in halves.iter() {
+                let pad = 0.5 * self.curve.width_at(*s0).max(self.curve.width_at(*s1));
+                let mut min = cp[0];
+                let mut max = cp[0];
+                for p in cp.iter() {
+                    for axis in 0..3 {
+                        min[axis] = min[axis].min(p[axis]);
+                        max[axis] = max[axis].max(p[axis]);
+                    }
+                }
+                if min.x() - pad > 0.0
+                    || max.x() + pad < 0.0
+                    || min.y() - pad > 0.0
+                    || max.y() + pad < 0.0
+                    || min.z() - pad > self.z_max
+                    || max.z() + pad < self.z_min
+                {
+                    continue;
+                }
+                if self.recurse(cp, *s0, *s1, depth - 1) {
+                    return true;
+                }
+            }
+            return false;
+        }
+
+        // Only hits between the perpendiculars to the piece at its ends count, so that
+        // neighbouring pieces do not both report them.
+        let edge = (cp[1].y() - cp[0].y()) * -cp[0].y() + cp[0].x() * (cp[0].x() - cp[1].x());
+        if edge < 0.0 {
+            return false;
+        }
+        let edge = (cp[2].y() - cp[3].y()) * -cp[3].y() + cp[3].x() * (cp[3].x() - cp[2].x());
+        if edge < 0.0 {
+            return false;
+        }
+
+        // Closest point to the ray on the line through the ends of the piece.
+        let (dx, dy) = (cp[3].x() - cp[0].x(), cp[3].y() - cp[0].y());
+        let denom = dx * dx + dy * dy;
+        if denom == 0.0 {
+            return false;
+        }
+        let w = (-cp[0].x() * dx - cp[0].y() * dy) / denom;
+        let s = ((1.0 - w) * s0 + w * s1).clamp(s0, s1);
+        let mut width = self.curve.width_at(s);
+        if let CurveKind::Ribbon(n0, n1) = self.curve.kind {
+            width *= dot(slerp(s, n0, n1), self.r.direction()).abs() / self.length;
+        }
+
+        let (pc, dpcdw) = eval_bezier(cp, w.clamp(0.0, 1.0));
+        let distance_squared = pc.x() * pc.x() + pc.y() * pc.y();
+        if distance_squared > 0.25 * width * width || pc.z() <= self.z_min || pc.z() >= self.z_max {
+            return false;
+        }
+
+        // v grows towards the left of the curve as seen along the ray.
+        let offset = distance_squared.sqrt() / width;
+        let v = if dpcdw.x() * -pc.y() + pc.x() * dpcdw.y() > 0.0 {
+            0.5 + offset
+        } else {
+            0.5 - offset
+        };
+        self.z_max = pc.z();
+        self.hit = Some(CurveHit {
+            t: pc.z() / self.length,
+            s,
+            v,
+            width,
+        });
+        self.any
+    }
+}
+
+impl Shape for Curve {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let hit = match self.intersect(&r, t_min, t_max, false) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let (u0, u1) = self.u_range;
+        let dpdu = self.eval(hit.s).1 / (u1 - u0);
+
+        let dpdv = match self.kind {
+            CurveKind::Ribbon(n0, n1) => unit_vector(cross(slerp(hit.s, n0, n1), dpdu)) * hit.width,
+            CurveKind::Flat | CurveKind::Tube => {
+                // Across the curve, perpendicular to the ray.
+                let frame = Onb::build_from_w(r.direction());
+                let along = frame.to_local(dpdu);
+                let across = unit_vector(Vec3::new(-along.y(), along.x(), 0.0)) * hit.width;
+                let across = match self.kind {
+                    CurveKind::Tube => {
+                        // Turn about the curve by -90 to 90 degrees across the width.
+                        let theta = (hit.v - 0.5) * std::f64::consts::PI;
+                        let axis = unit_vector(along);
+                        across * theta.cos() + cross(axis, across) * theta.sin()
+                    }
+                    _ => across,
+                };
+                frame.local(across)
+            }
+        };
+
+        rec.t = hit.t;
+        rec.p = r.at(hit.t);
+        rec.set_face_normal(r, unit_vector(cross(dpdu, dpdv)));
+        rec.u = (1.0 - hit.s) * u0 + hit.s * u1;
+        rec.v = hit.v;
+        rec.dpdu = dpdu;
+        rec.dpdv = dpdv;
+        true
+    }
+
+    fn occluded(&self, r: &Ray, t_max: f64) -> bool {
+        self.intersect(r, SHADOW_EPSILON, t_max, true).is_some()
+    }
+
+    // Approximated by the length of the control polygon.
+    fn area(&self) -> f64 {
+        let length: f64 = (0..3).map(|i| (self.cp[i + 1] - self.cp[i]).length()).sum();
+        0.5 * (self.width.0 + self.width.1) * length
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let pad = 0.5 * self.max_width();
+        let pad = Vec3::new(pad, pad, pad);
+        Some(self.cp.iter().fold(Aabb::empty(), |acc, &p| {
+            acc.union(&Aabb::new(p - pad, p + pad))
+        }))
+    }
+}
+
+// Many curves sharing a material, such as the strands of a head of hair, in a hierarchy of
+// their own.
+pub struct Curves {
+    curves: Vec<Curve>,
+    bvh: Bvh,
+}
+
+impl Curves {
+    pub fn new(curves: Vec<Curve>) -> Self {
+        let bvh = Bvh::build(
+            curves
+                .iter()
+                .enumerate()
+                .filter_map(|(i, c)| Some((i, c.bounding_box()?)))
+                .collect(),
+        );
+        Self { curves, bvh }
+    }
+}
+
+impl Shape for Curves {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut hit = false;
+        self.bvh.traverse(&r, t_min, t_max, |i, t_max| {
+            if self.curves[i].hit(r, t_min, *t_max, rec) {
+                *t_max = rec.t;
+                hit = true;
+            }
+            true
+        });
+        hit
+    }
+
+    fn occluded(&self, r: &Ray, t_max: f64) -> bool {
+        let mut occluded = false;
+        self.bvh.traverse(r, SHADOW_EPSILON, t_max, |i, _| {
+            occluded = self.curves[i].occluded(r, t_max);
+            !occluded
+        });
+        occluded
+    }
+
+    fn area(&self) -> f64 {
+        self.curves.iter().map(|c| c.area()).sum()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if self.curves.is_empty() {
+            return None;
+        }
+        Some(self.bvh.bounds())
+    }
+}
+
+// Position and derivative of the cubic Bezier curve `cp` at `s`, by de Casteljau's algorithm.
+fn eval_bezier(cp: &[Point3; 4], s: f64) -> (Point3, Vec3) {
+    let lerp = |a: Point3, b: Point3| (1.0 - s) * a + s * b;
+    let cp1 = [lerp(cp[0], cp[1]), lerp(cp[1], cp[2]), lerp(cp[2], cp[3])];
+    let cp2 = [lerp(cp1[0], cp1[1]), lerp(cp1[1], cp1[2])];
+    let derivative = cp2[1] - cp2[0];
+    // Coincident control points at an end leave the derivative there to the far ones.
+    let derivative = if derivative.length_squared() > 0.0 {
+        3.0 * derivative
+    } else {
+        cp[3] - cp[0]
+    };
+    (lerp(cp2[0], cp2[1]), derivative)
+}
+
+// Control points of the halves of `cp`, sharing the middle one.
+fn split_bezier(cp: &[Point3; 4]) -> [Point3; 7] {
+    [
+        cp[0],
+        (cp[0] + cp[1]) / 2.0,
+        (cp[0] + 2.0 * cp[1] + cp[2]) / 4.0,
+        (cp[0] + 3.0 * cp[1] + 3.0 * cp[2] + cp[3]) / 8.0,
+        (cp[1] + 2.0 * cp[2] + cp[3]) / 4.0,
+        (cp[2] + cp[3]) / 2.0,
+        cp[3],
+    ]
+}
+
+// Spherical interpolation between the unit vectors `a` and `b`.
+fn slerp(s: f64, a: Vec3, b: Vec3) -> Vec3 {
+    let angle = dot(a, b).clamp(-1.0, 1.0).acos();
+    if angle < 1e-6 {
+        return unit_vector((1.0 - s) * a + s * b);
+    }
+    (((1.0 - s) * angle).sin() * a + (s * angle).sin() * b) / angle.sin()
+}
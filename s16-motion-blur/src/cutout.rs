@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Material, Shape, SHADOW_EPSILON},
+    material::Lambertian,
+    ray::Ray,
+    texture::Texture,
+    util::random_f64,
+    vec3::{Color, Point3, Vec3},
+};
+
+// Cuts holes into a shape with an opacity texture, for leaves, fences and decals. Hits where
+// the shape is transparent are skipped inside `hit` and `occluded`, so camera rays, shadow
+// rays and the integrators built on them all see the same holes.
+//
+// By default a hit with opacity `a` is kept with probability `a`, which renders partial
+// opacity as a stochastic blend. With a threshold, hits are kept where `a` reaches it.
+pub struct Cutout {
+    shape: Arc<dyn Shape>,
+    opacity: Arc<dyn Texture>,
+    threshold: Option<f64>,
+    // Only fills the records of shadow ray tests.
+    placeholder: Arc<dyn Material>,
+}
+
+impl Cutout {
+    pub fn new<S: 'static + Shape, T: 'static + Texture>(shape: S, opacity: T) -> Self {
+        Self {
+            shape: Arc::new(shape),
+            opacity: Arc::new(opacity),
+            threshold: None,
+            placeholder: Arc::new(Lambertian::new(Color::new(0.0, 0.0, 0.0))),
+        }
+    }
+
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    fn is_opaque(&self, rec: &HitRecord) -> bool {
+        let alpha = self.opacity.value(rec.u, rec.v, rec.p).x();
+        match self.threshold {
+            Some(threshold) => alpha >= threshold,
+            None => alpha >= 1.0 || random_f64() < alpha,
+        }
+    }
+}
+
+impl Shape for Cutout {
+    fn hit(&self, r: Ray, mut t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        while self.shape.hit(r, t_min, t_max, rec) {
+            if self.is_opaque(rec) {
+                return true;
+            }
+            t_min = rec.t;
+        }
+        false
+    }
+
+    fn occluded(&self, r: &Ray, t_max: f64) -> bool {
+        let mut rec = HitRecord::new(Arc::clone(&self.placeholder));
+        self.hit(*r, SHADOW_EPSILON, t_max, &mut rec)
+    }
+
+    // Directions towards lights are sampled over the whole shape, holes included. Those
+    // through a hole find no hit, so they carry no light.
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.shape.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        self.shape.random(origin)
+    }
+
+    fn area(&self) -> f64 {
+        self.shape.area()
+    }
+
+    // Points in holes are rejected with the same opacity test as `hit`, so that light paths
+    // only leave where camera and shadow rays find the surface. The density stays that of
+    // the whole shape, which leaves the rejected points with no light.
+    fn sample_area(&self) -> Option<(Point3, Vec3)> {
+        let (p, n) = self.shape.sample_area()?;
+        // The texture coordinates of the point come from a ray just outside it.
+        let mut rec = HitRecord::new(Arc::clone(&self.placeholder));
+        let probe = Ray::new(p + SHADOW_EPSILON * n, -n);
+        if !self.shape.hit(probe, 0.0, 2.0 * SHADOW_EPSILON, &mut rec) || !self.is_opaque(&rec) {
+            return None;
+        }
+        Some((p, n))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.shape.bounding_box()
+    }
+
+    fn perturbs_normals(&self) -> bool {
+        self.shape.perturbs_normals()
+    }
+}
@@ -0,0 +1,248 @@
+use std::f64::consts::PI;
+
+use crate::{
+    aabb::Aabb,
+    disk::circle_bounds,
+    hittable::{area_pdf, HitRecord, Shape, SHADOW_EPSILON},
+    onb::Onb,
+    polynomial::solve_quadratic,
+    ray::Ray,
+    util::random_f64,
+    vec3::{unit_vector, Point3, Vec3},
+};
+
+#[derive(Clone, Copy)]
+enum Part {
+    Side,
+    Base,
+    Top,
+}
+
+// A cylinder between the centres `base` and `top` of its ends, or with different radii at the
+// ends a cone or frustum, closed by disks unless built `without_caps`. On the side, u is the
+// angle around the axis and v runs from the base to the top; on the caps, u is the angle and v
+// runs out from the axis. All go from 0 to 1.
+pub struct Cylinder {
+    base: Point3,
+    // w along the axis, towards the top.
+    frame: Onb,
+    height: f64,
+    base_radius: f64,
+    top_radius: f64,
+    caps: bool,
+}
+
+impl Cylinder {
+    pub fn new(base: Point3, top: Point3, radius: f64) -> Self {
+        Self::frustum(base, top, radius, radius)
+    }
+
+    // A cone with its tip at `apex`.
+    pub fn cone(base: Point3, apex: Point3, radius: f64) -> Self {
+        Self::frustum(base, apex, radius, 0.0)
+    }
+
+    pub fn frustum(base: Point3, top: Point3, base_radius: f64, top_radius: f64) -> Self {
+        let height = (top - base).length();
+        assert!(height > 0.0, "cylinder ends coincide");
+        assert!(
+            base_radius >= 0.0 && top_radius >= 0.0,
+            "negative cylinder radius"
+        );
+        Self {
+            base,
+            frame: Onb::build_right_handed(top - base),
+            height,
+            base_radius,
+            top_radius,
+            caps: true,
+        }
+    }
+
+    pub fn without_caps(mut self) -> Self {
+        self.caps = false;
+        self
+    }
+
+    // Change of the radius per unit of height.
+    fn slope(&self) -> f64 {
+        (self.top_radius - self.base_radius) / self.height
+    }
+
+    fn radius_at(&self, z: f64) -> f64 {
+        self.base_radius + self.slope() * z
+    }
+
+    fn side_area(&self) -> f64 {
+        let dr = self.top_radius - self.base_radius;
+        PI * (self.base_radius + self.top_radius) * (dr * dr + self.height * self.height).sqrt()
+    }
+
+    fn cap_area(&self, part: Part) -> f64 {
+        let radius = match part {
+            Part::Base if self.caps => self.base_radius,
+            Part::Top if self.caps => self.top_radius,
+            _ => 0.0,
+        };
+        PI * radius * radius
+    }
+
+    // Ray parameter, point in the frame of the axis and part of the nearest intersection with
+    // `r`.
+    fn intersect(&self, r: &Ray, t_min: f64, mut t_max: f64) -> Option<(f64, Point3, Part)> {
+        let o = self.frame.to_local(r.origin() - self.base);
+        let d = self.frame.to_local(r.direction());
+        let mut hit = None;
+
+        // x^2 + y^2 = (r0 + k z)^2 between the ends.
+        let k = self.slope();
+        let rz = self.base_radius + k * o.z();
+        let roots = solve_quadratic(
+            o.x() * o.x() + o.y() * o.y() - rz * rz,
+            2.0 * (o.x() * d.x() + o.y() * d.y() - k * d.z() * rz),
+            d.x() * d.x() + d.y() * d.y() - k * k * d.z() * d.z(),
+        );
+        for t in roots {
+            let z = o.z() + t * d.z();
+            if t > t_min && t < t_max && (0.0..=self.height).contains(&z) {
+                t_max = t;
+                hit = Some((t, o + t * d, Part::Side));
+                break;
+            }
+        }
+
+        if self.caps && d.z() != 0.0 {
+            for (part, z, radius) in [
+                (Part::Base, 0.0, self.base_radius),
+                (Part::Top, self.height, self.top_radius),
+            ] {
+                let t = (z - o.z()) / d.z();
+                if t <= t_min || t >= t_max {
+                    continue;
+                }
+                let q = o + t * d;
+                if q.x() * q.x() + q.y() * q.y() <= radius * radius {
+                    t_max = t;
+                    hit = Some((t, q, part));
+                }
+            }
+        }
+        hit
+    }
+
+    // Outward normal at the point `q` in the frame of the axis.
+    fn normal(&self, q: Point3, part: Part) -> Vec3 {
+        match part {
+            Part::Side => self.frame.local(unit_vector(Vec3::new(
+                q.x(),
+                q.y(),
+                -self.slope() * self.radius_at(q.z()),
+            ))),
+            Part::Base => -self.frame.w,
+            Part::Top => self.frame.w,
+        }
+    }
+
+    fn side_point(&self, z: f64, phi: f64) -> Point3 {
+        let radius = self.radius_at(z);
+        Vec3::new(radius * phi.cos(), radius * phi.sin(), z)
+    }
+}
+
+impl Shape for Cylinder {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (t, q, part) = match self.intersect(&r, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        rec.t = t;
+        rec.p = r.at(t);
+        rec.set_face_normal(r, self.normal(q, part));
+
+        let phi = q.y().atan2(q.x()).rem_euclid(2.0 * PI);
+        let (cos_phi, sin_phi) = (phi.cos(), phi.sin());
+        let around = 2.0 * PI * Vec3::new(-q.y(), q.x(), 0.0);
+        let outwards = Vec3::new(cos_phi, sin_phi, 0.0);
+        rec.u = phi / (2.0 * PI);
+        let (dpdu, dpdv) = match part {
+            Part::Side => {
+                rec.v = q.z() / self.height;
+                (
+                    around,
+                    self.height * Vec3::new(self.slope() * cos_phi, self.slope() * sin_phi, 1.0),
+                )
+            }
+            Part::Base => {
+                rec.v = (q.x() * q.x() + q.y() * q.y()).sqrt() / self.base_radius;
+                (around, self.base_radius * outwards)
+            }
+            // The angle runs the other way on the top, keeping the tangents right-handed
+            // about the outward normal.
+            Part::Top => {
+                rec.u = 1.0 - rec.u;
+                rec.v = (q.x() * q.x() + q.y() * q.y()).sqrt() / self.top_radius;
+                (-around, self.top_radius * outwards)
+            }
+        };
+        rec.dpdu = self.frame.local(dpdu);
+        rec.dpdv = self.frame.local(dpdv);
+        true
+    }
+
+    fn occluded(&self, r: &Ray, t_max: f64) -> bool {
+        self.intersect(r, SHADOW_EPSILON, t_max).is_some()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let r = Ray::new(origin, direction);
+        area_pdf(&r, self.area(), |t_min| {
+            let (t, q, part) = self.intersect(&r, t_min, f64::INFINITY)?;
+            Some((t, self.normal(q, part)))
+        })
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        self.sample_area().unwrap().0 - origin
+    }
+
+    fn area(&self) -> f64 {
+        self.side_area() + self.cap_area(Part::Base) + self.cap_area(Part::Top)
+    }
+
+    fn sample_area(&self) -> Option<(Point3, Vec3)> {
+        let phi = 2.0 * PI * random_f64();
+        let mut u = random_f64() * self.area();
+        let (q, part) = if u < self.side_area() {
+            // The area up to a height grows with the square of the radius there.
+            let (r0, r1) = (self.base_radius, self.top_radius);
+            let z = if r0 == r1 {
+                random_f64() * self.height
+            } else {
+                let radius = (r0 * r0 + random_f64() * (r1 * r1 - r0 * r0)).sqrt();
+                (radius - r0) / self.slope()
+            };
+            (self.side_point(z, phi), Part::Side)
+        } else {
+            u -= self.side_area();
+            let (part, z, radius) = if u < self.cap_area(Part::Base) {
+                (Part::Base, 0.0, self.base_radius)
+            } else {
+                (Part::Top, self.height, self.top_radius)
+            };
+            let radius = radius * random_f64().sqrt();
+            (Vec3::new(radius * phi.cos(), radius * phi.sin(), z), part)
+        };
+        Some((self.base + self.frame.local(q), self.normal(q, part)))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let top = self.base + self.height * self.frame.w;
+        Some(
+            circle_bounds(self.base, self.frame.w, self.base_radius).union(&circle_bounds(
+                top,
+                self.frame.w,
+                self.top_radius,
+            )),
+        )
+    }
+}
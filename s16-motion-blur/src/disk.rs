@@ -0,0 +1,129 @@
+use std::f64::consts::PI;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{area_pdf, HitRecord, Shape, SHADOW_EPSILON},
+    onb::Onb,
+    ray::Ray,
+    util::random_f64,
+    vec3::{unit_vector, Point3, Vec3},
+};
+
+// Disk facing along `normal`, or with a hole in the middle an annulus. u is the angle around
+// the centre and v runs from the outer edge to the inner one, both from 0 to 1.
+pub struct Disk {
+    center: Point3,
+    frame: Onb,
+    inner_radius: f64,
+    outer_radius: f64,
+}
+
+impl Disk {
+    pub fn new(center: Point3, normal: Vec3, radius: f64) -> Self {
+        Self::annulus(center, normal, 0.0, radius)
+    }
+
+    pub fn annulus(center: Point3, normal: Vec3, inner_radius: f64, outer_radius: f64) -> Self {
+        assert!(
+            0.0 <= inner_radius && inner_radius < outer_radius,
+            "annulus radii out of order"
+        );
+        Self {
+            center,
+            frame: Onb::build_right_handed(normal),
+            inner_radius,
+            outer_radius,
+        }
+    }
+
+    // Ray parameter and point in the frame of the disk of the intersection with `r`.
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, Point3)> {
+        let o = self.frame.to_local(r.origin() - self.center);
+        let d = self.frame.to_local(r.direction());
+        if d.z().abs() < 1e-12 {
+            return None;
+        }
+        let t = -o.z() / d.z();
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        let q = o + t * d;
+        let distance_squared = q.x() * q.x() + q.y() * q.y();
+        if distance_squared > self.outer_radius * self.outer_radius
+            || distance_squared < self.inner_radius * self.inner_radius
+        {
+            return None;
+        }
+        Some((t, q))
+    }
+
+    fn point(&self, radius: f64, phi: f64) -> Point3 {
+        self.center
+            + self
+                .frame
+                .local(Vec3::new(radius * phi.cos(), radius * phi.sin(), 0.0))
+    }
+}
+
+impl Shape for Disk {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (t, q) = match self.intersect(&r, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        rec.t = t;
+        rec.p = r.at(t);
+        rec.set_face_normal(r, self.frame.w);
+
+        let radius = (q.x() * q.x() + q.y() * q.y()).sqrt();
+        let phi = q.y().atan2(q.x()).rem_euclid(2.0 * PI);
+        let width = self.outer_radius - self.inner_radius;
+        rec.u = phi / (2.0 * PI);
+        rec.v = (self.outer_radius - radius) / width;
+        rec.dpdu = self.frame.local(2.0 * PI * Vec3::new(-q.y(), q.x(), 0.0));
+        let (cos_phi, sin_phi) = (phi.cos(), phi.sin());
+        rec.dpdv = self.frame.local(-width * Vec3::new(cos_phi, sin_phi, 0.0));
+        true
+    }
+
+    fn occluded(&self, r: &Ray, t_max: f64) -> bool {
+        self.intersect(r, SHADOW_EPSILON, t_max).is_some()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let r = Ray::new(origin, direction);
+        area_pdf(&r, self.area(), |t_min| {
+            let (t, _) = self.intersect(&r, t_min, f64::INFINITY)?;
+            Some((t, self.frame.w))
+        })
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        self.sample_area().unwrap().0 - origin
+    }
+
+    fn area(&self) -> f64 {
+        PI * (self.outer_radius * self.outer_radius - self.inner_radius * self.inner_radius)
+    }
+
+    fn sample_area(&self) -> Option<(Point3, Vec3)> {
+        let (r0, r1) = (self.inner_radius, self.outer_radius);
+        let radius = (r0 * r0 + random_f64() * (r1 * r1 - r0 * r0)).sqrt();
+        let phi = 2.0 * PI * random_f64();
+        Some((self.point(radius, phi), self.frame.w))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(circle_bounds(self.center, self.frame.w, self.outer_radius))
+    }
+}
+
+// Bounds of the circle of `radius` around `center` facing along `normal`.
+pub fn circle_bounds(center: Point3, normal: Vec3, radius: f64) -> Aabb {
+    let n = unit_vector(normal);
+    let mut extent = Vec3::new(0.0, 0.0, 0.0);
+    for axis in 0..3 {
+        extent[axis] = radius * (1.0 - n[axis] * n[axis]).max(0.0).sqrt();
+    }
+    Aabb::new(center - extent, center + extent)
+}
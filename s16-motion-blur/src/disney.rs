@@ -0,0 +1,444 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    hittable::{HitRecord, Material},
+    medium_stack::Interior,
+    microfacet::{fresnel_dielectric, reflect_local, refract_local, TrowbridgeReitz},
+    onb::Onb,
+    ray::Ray,
+    texture::Texture,
+    util::random_f64,
+    vec3::{dot, unit_vector, Color, Vec3},
+};
+
+// Disney's principled BSDF (Burley, "Physically Based Shading at Disney", 2012, and "Extending
+// the Disney BRDF to a BSDF with Integrated Subsurface Scattering", 2015) without the
+// subsurface and thin-surface extensions. All parameters are textures; scalar ones read the
+// first channel and are expected in [0, 1].
+//
+// The lobes are a Burley diffuse with a sheen term, an anisotropic GGX specular reflection, a
+// GTR1 clearcoat and a GGX transmission. `scatter` picks one lobe to sample and weights the
+// result by the density of the whole mixture, so `eval` / `pdf` always match it.
+pub struct Disney {
+    base_color: Arc<dyn Texture>,
+    metallic: Arc<dyn Texture>,
+    roughness: Arc<dyn Texture>,
+    specular: Arc<dyn Texture>,
+    specular_tint: Arc<dyn Texture>,
+    sheen: Arc<dyn Texture>,
+    sheen_tint: Arc<dyn Texture>,
+    clearcoat: Arc<dyn Texture>,
+    clearcoat_gloss: Arc<dyn Texture>,
+    transmission: Arc<dyn Texture>,
+    anisotropic: Arc<dyn Texture>,
+    // Index of refraction of the interior, only used when the material transmits.
+    ior: f64,
+    transmissive: bool,
+}
+
+impl Disney {
+    pub fn new<T: 'static + Texture>(base_color: T) -> Self {
+        Self {
+            base_color: Arc::new(base_color),
+            metallic: Arc::new(0.0),
+            roughness: Arc::new(0.5),
+            specular: Arc::new(0.5),
+            specular_tint: Arc::new(0.0),
+            sheen: Arc::new(0.0),
+            sheen_tint: Arc::new(0.5),
+            clearcoat: Arc::new(0.0),
+            clearcoat_gloss: Arc::new(1.0),
+            transmission: Arc::new(0.0),
+            anisotropic: Arc::new(0.0),
+            ior: 1.5,
+            transmissive: false,
+        }
+    }
+
+    pub fn with_metallic<T: 'static + Texture>(mut self, metallic: T) -> Self {
+        self.metallic = Arc::new(metallic);
+        self
+    }
+
+    pub fn with_roughness<T: 'static + Texture>(mut self, roughness: T) -> Self {
+        self.roughness = Arc::new(roughness);
+        self
+    }
+
+    // Scales the normal incidence reflectance of the dielectric base, 0.5 being 4%.
+    pub fn with_specular<T: 'static + Texture>(mut self, specular: T) -> Self {
+        self.specular = Arc::new(specular);
+        self
+    }
+
+    pub fn with_specular_tint<T: 'static + Texture>(mut self, specular_tint: T) -> Self {
+        self.specular_tint = Arc::new(specular_tint);
+        self
+    }
+
+    pub fn with_sheen<T: 'static + Texture>(mut self, sheen: T) -> Self {
+        self.sheen = Arc::new(sheen);
+        self
+    }
+
+    pub fn with_sheen_tint<T: 'static + Texture>(mut self, sheen_tint: T) -> Self {
+        self.sheen_tint = Arc::new(sheen_tint);
+        self
+    }
+
+    pub fn with_clearcoat<T: 'static + Texture>(mut self, clearcoat: T) -> Self {
+        self.clearcoat = Arc::new(clearcoat);
+        self
+    }
+
+    pub fn with_clearcoat_gloss<T: 'static + Texture>(mut self, clearcoat_gloss: T) -> Self {
+        self.clearcoat_gloss = Arc::new(clearcoat_gloss);
+        self
+    }
+
+    // Makes the surface the boundary of a solid with index of refraction `ior`, see
+    // `medium_stack`.
+    pub fn with_transmission<T: 'static + Texture>(mut self, transmission: T, ior: f64) -> Self {
+        self.transmission = Arc::new(transmission);
+        self.ior = ior;
+        self.transmissive = true;
+        self
+    }
+
+    // Roughens the specular lobe more along the surface's u direction (dpdu) than across it.
+    // Shapes without a parameterization have no such direction, and the stretch follows an
+    // arbitrary tangent on them.
+    pub fn with_anisotropic<T: 'static + Texture>(mut self, anisotropic: T) -> Self {
+        self.anisotropic = Arc::new(anisotropic);
+        self
+    }
+
+    fn lobes(&self, rec: &HitRecord) -> Lobes {
+        let scalar = |t: &Arc<dyn Texture>| t.value(rec.u, rec.v, rec.p).x().clamp(0.0, 1.0);
+        let base = self.base_color.value(rec.u, rec.v, rec.p);
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness);
+        let transmission = if self.transmissive {
+            scalar(&self.transmission)
+        } else {
+            0.0
+        };
+
+        let white = Color::new(1.0, 1.0, 1.0);
+        let luminance = 0.3 * base.x() + 0.6 * base.y() + 0.1 * base.z();
+        let tint = if luminance > 0.0 {
+            base / luminance
+        } else {
+            white
+        };
+        let dielectric_spec =
+            0.08 * scalar(&self.specular) * lerp(scalar(&self.specular_tint), white, tint);
+
+        let aspect = (1.0 - 0.9 * scalar(&self.anisotropic)).sqrt();
+        let alpha = roughness * roughness;
+        let clearcoat = scalar(&self.clearcoat);
+
+        let diffuse_weight = (1.0 - metallic) * (1.0 - transmission);
+        let transmission_weight = (1.0 - metallic) * transmission;
+        let specular_weight = metallic + 0.25 * (1.0 - metallic);
+        let clearcoat_weight = 0.25 * clearcoat;
+        let total = diffuse_weight + specular_weight + transmission_weight + clearcoat_weight;
+
+        Lobes {
+            base,
+            roughness,
+            spec0: lerp(metallic, dielectric_spec, base),
+            sheen: scalar(&self.sheen) * lerp(scalar(&self.sheen_tint), white, tint),
+            clearcoat,
+            clearcoat_alpha: lerp(scalar(&self.clearcoat_gloss), 0.1, 0.001),
+            distribution: TrowbridgeReitz::anisotropic(
+                (alpha / aspect).max(0.001),
+                (alpha * aspect).max(0.001),
+            ),
+            diffuse_weight,
+            transmission_weight,
+            eta: rec.transmitted_ior / rec.ior,
+            p_diffuse: diffuse_weight / total,
+            p_specular: specular_weight / total,
+            p_clearcoat: clearcoat_weight / total,
+        }
+    }
+}
+
+// Parameters evaluated at one hit point.
+struct Lobes {
+    base: Color,
+    roughness: f64,
+    spec0: Color,
+    sheen: Color,
+    clearcoat: f64,
+    clearcoat_alpha: f64,
+    distribution: TrowbridgeReitz,
+    diffuse_weight: f64,
+    transmission_weight: f64,
+    // n_t / n_i across the surface.
+    eta: f64,
+    // Lobe selection probabilities; transmission takes the remainder.
+    p_diffuse: f64,
+    p_specular: f64,
+    p_clearcoat: f64,
+}
+
+impl Lobes {
+    // Returns (f * |cos_i|, pdf) for local directions.
+    fn eval_pdf(&self, wo: Vec3, wi: Vec3) -> (Color, f64) {
+        let black = (Color::new(0.0, 0.0, 0.0), 0.0);
+        let (cos_o, cos_i) = (wo.z(), wi.z());
+        if cos_o <= 0.0 || cos_i == 0.0 {
+            return black;
+        }
+        if cos_i > 0.0 {
+            self.eval_pdf_reflection(wo, wi)
+        } else if self.transmission_weight > 0.0 {
+            self.eval_pdf_transmission(wo, wi)
+        } else {
+            black
+        }
+    }
+
+    fn eval_pdf_reflection(&self, wo: Vec3, wi: Vec3) -> (Color, f64) {
+        let (cos_o, cos_i) = (wo.z(), wi.z());
+        let wh = wo + wi;
+        if wh.length_squared() == 0.0 {
+            return (Color::new(0.0, 0.0, 0.0), 0.0);
+        }
+        let wh = unit_vector(wh);
+        let cos_d = dot(wi, wh);
+
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+        let diffuse = self.base / PI
+            * ((1.0 + (fd90 - 1.0) * schlick_weight(cos_i))
+                * (1.0 + (fd90 - 1.0) * schlick_weight(cos_o)));
+        let sheen = schlick_weight(cos_d) * self.sheen;
+        let mut f = self.diffuse_weight * (diffuse + sheen);
+
+        let fresnel = lerp(schlick_weight(cos_d), self.spec0, Color::new(1.0, 1.0, 1.0));
+        let specular =
+            self.distribution.d(wh) * self.distribution.g(wo, wi) / (4.0 * cos_o * cos_i);
+        f += specular * fresnel;
+
+        let mut pdf = self.p_diffuse * cos_i / PI
+            + self.p_specular * self.distribution.pdf(wo, wh) / (4.0 * dot(wo, wh));
+
+        if self.clearcoat > 0.0 {
+            let d = gtr1(wh.z(), self.clearcoat_alpha);
+            let fresnel = 0.04 + 0.96 * schlick_weight(cos_d);
+            let g = TrowbridgeReitz::new(0.25).g(wo, wi);
+            let c = 0.25 * self.clearcoat * d * fresnel * g / (4.0 * cos_o * cos_i);
+            f += Color::new(c, c, c);
+            pdf += self.p_clearcoat * d * wh.z() / (4.0 * dot(wo, wh));
+        }
+
+        (f * cos_i, pdf)
+    }
+
+    // Rough dielectric transmission tinted by the base colour. The tint is applied at both the
+    // entry and the exit, hence the square root.
+    fn eval_pdf_transmission(&self, wo: Vec3, wi: Vec3) -> (Color, f64) {
+        let black = (Color::new(0.0, 0.0, 0.0), 0.0);
+        let (cos_o, cos_i) = (wo.z(), wi.z());
+        let wm = self.eta * wi + wo;
+        if wm.length_squared() == 0.0 {
+            return black;
+        }
+        let wm = unit_vector(wm);
+        let wm = if wm.z() < 0.0 { -wm } else { wm };
+        if dot(wm, wi) * cos_i < 0.0 || dot(wm, wo) * cos_o < 0.0 {
+            return black;
+        }
+
+        let fresnel = fresnel_dielectric(dot(wo, wm), self.eta);
+        let denom = (dot(wi, wm) + dot(wo, wm) / self.eta).powi(2);
+        let f = (1.0 - fresnel)
+            * self.distribution.d(wm)
+            * self.distribution.g(wo, wi)
+            * (dot(wi, wm) * dot(wo, wm)).abs()
+            / (denom * cos_o);
+        let tint = Color::new(
+            self.base.x().sqrt(),
+            self.base.y().sqrt(),
+            self.base.z().sqrt(),
+        );
+        let p_transmission = 1.0 - self.p_diffuse - self.p_specular - self.p_clearcoat;
+        let pdf = p_transmission * self.distribution.pdf(wo, wm) * dot(wi, wm).abs() / denom;
+        (self.transmission_weight * f * tint, pdf)
+    }
+
+    // Directions on the wrong side for their lobe are dropped, as `eval_pdf` would score them
+    // with the density of the lobes on that side.
+    fn sample(&self, wo: Vec3) -> Option<Vec3> {
+        let u = random_f64();
+        let (wi, reflected) = if u < self.p_diffuse {
+            (Vec3::random_cosine_direction(), true)
+        } else if u < self.p_diffuse + self.p_specular {
+            let wm = self.distribution.sample_wm(wo, random_f64(), random_f64());
+            (reflect_local(wo, wm), true)
+        } else if u < self.p_diffuse + self.p_specular + self.p_clearcoat {
+            (reflect_local(wo, sample_gtr1(self.clearcoat_alpha)), true)
+        } else if self.transmission_weight > 0.0 {
+            let wm = self.distribution.sample_wm(wo, random_f64(), random_f64());
+            (refract_local(wo, wm, self.eta)?, false)
+        } else {
+            return None;
+        };
+        if (wi.z() > 0.0) != reflected || wi.z() == 0.0 {
+            return None;
+        }
+        Some(wi)
+    }
+}
+
+impl Material for Disney {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        let uvw = Onb::build_from_w_and_tangent(rec.normal, rec.dpdu);
+        let wo = uvw.to_local(-unit_vector(r_in.direction()));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let lobes = self.lobes(rec);
+        let wi = match lobes.sample(wo) {
+            Some(wi) => wi,
+            None => return false,
+        };
+        let (f, pdf) = lobes.eval_pdf(wo, wi);
+        if pdf <= 0.0 {
+            return false;
+        }
+
+        *attenuation = f / pdf;
+        *scattered = Ray::new(rec.p, uvw.local(wi));
+        true
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let uvw = Onb::build_from_w_and_tangent(rec.normal, rec.dpdu);
+        self.lobes(rec)
+            .eval_pdf(uvw.to_local(wo), uvw.to_local(wi))
+            .0
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let uvw = Onb::build_from_w_and_tangent(rec.normal, rec.dpdu);
+        self.lobes(rec)
+            .eval_pdf(uvw.to_local(wo), uvw.to_local(wi))
+            .1
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn interior(&self, _wavelength: Option<f64>) -> Option<Interior> {
+        if !self.transmissive {
+            return None;
+        }
+        Some(Interior {
+            priority: 0,
+            ior: self.ior,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            scattering: Color::new(0.0, 0.0, 0.0),
+            g: 0.0,
+        })
+    }
+}
+
+fn lerp<T>(t: f64, a: T, b: T) -> T
+where
+    T: std::ops::Mul<f64, Output = T> + std::ops::Add<Output = T>,
+{
+    a * (1.0 - t) + b * t
+}
+
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+// Burley's generalized Trowbridge-Reitz with exponent 1, used for the clearcoat.
+fn gtr1(cos_theta: f64, alpha: f64) -> f64 {
+    if alpha >= 1.0 {
+        return 1.0 / PI;
+    }
+    let a2 = alpha * alpha;
+    (a2 - 1.0) / (PI * a2.ln() * (1.0 + (a2 - 1.0) * cos_theta * cos_theta))
+}
+
+fn sample_gtr1(alpha: f64) -> Vec3 {
+    let a2 = alpha * alpha;
+    let cos2 = ((1.0 - a2.powf(1.0 - random_f64())) / (1.0 - a2)).clamp(0.0, 1.0);
+    let sin_theta = (1.0 - cos2).sqrt();
+    let phi = 2.0 * PI * random_f64();
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos2.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Compares the fraction of `sample` calls landing in each cell of a grid over the sphere,
+    // equal in z and in azimuth so that cells have equal solid angle, with the integral of the
+    // pdf over the cell.
+    #[test]
+    fn sample_matches_pdf() {
+        const Z_CELLS: usize = 16;
+        const PHI_CELLS: usize = 16;
+        const SUBDIVISIONS: usize = 16;
+        const SAMPLES: usize = 400_000;
+
+        let disney = Disney::new(Color::new(0.8, 0.6, 0.4))
+            .with_roughness(0.6)
+            .with_clearcoat(1.0)
+            .with_clearcoat_gloss(0.5)
+            .with_transmission(0.7, 1.5);
+        let mut rec = HitRecord::new(Arc::new(Disney::new(Color::new(0.0, 0.0, 0.0))));
+        rec.transmitted_ior = 1.5;
+        let lobes = disney.lobes(&rec);
+        let wo = unit_vector(Vec3::new(0.8, 0.1, 0.3));
+
+        let cell = |w: Vec3| {
+            let z = ((w.z() + 1.0) * 0.5 * Z_CELLS as f64) as usize;
+            let phi = w.y().atan2(w.x()).rem_euclid(2.0 * PI);
+            let phi = (phi / (2.0 * PI) * PHI_CELLS as f64) as usize;
+            z.min(Z_CELLS - 1) * PHI_CELLS + phi.min(PHI_CELLS - 1)
+        };
+        let mut histogram = vec![0.0; Z_CELLS * PHI_CELLS];
+        for _ in 0..SAMPLES {
+            if let Some(wi) = lobes.sample(wo) {
+                histogram[cell(unit_vector(wi))] += 1.0 / SAMPLES as f64;
+            }
+        }
+
+        let (dz, dphi) = (2.0 / Z_CELLS as f64, 2.0 * PI / PHI_CELLS as f64);
+        let n = (Z_CELLS * SUBDIVISIONS, PHI_CELLS * SUBDIVISIONS);
+        let mut integral = vec![0.0; Z_CELLS * PHI_CELLS];
+        for i in 0..n.0 {
+            for j in 0..n.1 {
+                let z = -1.0 + (i as f64 + 0.5) * 2.0 / n.0 as f64;
+                let phi = (j as f64 + 0.5) * 2.0 * PI / n.1 as f64;
+                let r = (1.0 - z * z).sqrt();
+                let wi = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+                let area = dz * dphi / (SUBDIVISIONS * SUBDIVISIONS) as f64;
+                integral[cell(wi)] += lobes.eval_pdf(wo, wi).1 * area;
+            }
+        }
+
+        for (k, (&sampled, &expected)) in histogram.iter().zip(integral.iter()).enumerate() {
+            let tolerance = 5.0 * (expected / SAMPLES as f64).sqrt() + 0.02 * expected + 1e-4;
+            assert!(
+                (sampled - expected).abs() < tolerance,
+                "cell {k}: sampled {sampled}, pdf {expected}"
+            );
+        }
+    }
+}
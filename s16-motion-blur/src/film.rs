@@ -0,0 +1,66 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::vec3::Color;
+
+// Accumulates contributions that land on arbitrary pixels, such as light tracing splats,
+// from many threads at once.
+pub struct Film {
+    width: usize,
+    height: usize,
+    splats: Vec<[AtomicU64; 3]>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        let splats = (0..width * height)
+            .map(|_| [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)])
+            .collect();
+        Self {
+            width,
+            height,
+            splats,
+        }
+    }
+
+    // Maps the `Camera::get_ray` coordinates back to the pixel index used by the renderer,
+    // whose rows run from the top of the image down.
+    pub fn pixel_index(&self, s: f64, t: f64) -> Option<usize> {
+        if !(0.0..1.0).contains(&s) || !(0.0..1.0).contains(&t) {
+            return None;
+        }
+        let x = (s * self.width as f64) as usize;
+        let y = (t * self.height as f64) as usize;
+        Some((self.height - 1 - y) * self.width + x)
+    }
+
+    pub fn add_splat(&self, s: f64, t: f64, c: Color) {
+        if !(c.x().is_finite() && c.y().is_finite() && c.z().is_finite()) {
+            return;
+        }
+        if let Some(idx) = self.pixel_index(s, t) {
+            for (i, v) in [c.x(), c.y(), c.z()].into_iter().enumerate() {
+                atomic_add(&self.splats[idx][i], v);
+            }
+        }
+    }
+
+    pub fn splat(&self, idx: usize) -> Color {
+        let [r, g, b] = &self.splats[idx];
+        Color::new(
+            f64::from_bits(r.load(Ordering::Relaxed)),
+            f64::from_bits(g.load(Ordering::Relaxed)),
+            f64::from_bits(b.load(Ordering::Relaxed)),
+        )
+    }
+}
+
+fn atomic_add(a: &AtomicU64, v: f64) {
+    let mut current = a.load(Ordering::Relaxed);
+    loop {
+        let new = (f64::from_bits(current) + v).to_bits();
+        match a.compare_exchange_weak(current, new, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => break,
+            Err(x) => current = x,
+        }
+    }
+}
@@ -0,0 +1,448 @@
+use std::{
+    f64::consts::{LN_2, PI},
+    sync::Arc,
+};
+
+use crate::{
+    hittable::{HitRecord, Material},
+    microfacet::fresnel_dielectric,
+    onb::Onb,
+    ray::Ray,
+    texture::Texture,
+    util::random_f64,
+    vec3::{cross, unit_vector, Color, Vec3},
+};
+
+// Scattering orders with lobes of their own: reflection, transmission and one internal
+// reflection. Higher orders are lumped into one more.
+const P_MAX: usize = 3;
+
+// Absorption coefficients of the two melanins, per unit concentration and fibre diameter.
+const EUMELANIN: [f64; 3] = [0.419, 0.697, 1.37];
+const PHEOMELANIN: [f64; 3] = [0.187, 0.4, 1.05];
+
+enum Absorption {
+    Coefficient(Color),
+    // Colour seen after multiple scattering, converted to a coefficient at each hit.
+    Color(Arc<dyn Texture>),
+}
+
+// Hair and fur after d'Eon et al. and Chiang et al. ("A Practical and Controllable Hair and
+// Fur Model for Production Path Tracing", 2016), as in pbrt. Light reflects off a fibre, passes
+// through it, or reflects inside it one or more times, and each of these orders has its own
+// lobes around the fibre and across it, shifted by the tilt of the cuticle scales.
+//
+// Fibres run along dpdu and v crosses them from one edge to the other, as on curves.
+pub struct Hair {
+    absorption: Absorption,
+    eta: f64,
+    // Longitudinal and azimuthal roughness.
+    beta_m: f64,
+    beta_n: f64,
+    // Tilt of the scales in degrees.
+    alpha: f64,
+}
+
+impl Hair {
+    // `sigma_a` absorbs per unit of fibre diameter.
+    pub fn new(sigma_a: Color) -> Self {
+        Self {
+            absorption: Absorption::Coefficient(sigma_a),
+            eta: 1.55,
+            beta_m: 0.3,
+            beta_n: 0.3,
+            alpha: 2.0,
+        }
+    }
+
+    // Natural hair by its concentrations of eumelanin, which darkens it from blond (around
+    // 0.3) through brown (1.3) to black (8), and of pheomelanin, which makes it red.
+    pub fn from_melanin(eumelanin: f64, pheomelanin: f64) -> Self {
+        let coefficient = |i: usize| eumelanin * EUMELANIN[i] + pheomelanin * PHEOMELANIN[i];
+        Self::new(Color::new(coefficient(0), coefficient(1), coefficient(2)))
+    }
+
+    // Hair that looks about `color`, such as dyed hair.
+    pub fn from_color<T: 'static + Texture>(color: T) -> Self {
+        let mut hair = Self::new(Color::new(0.0, 0.0, 0.0));
+        hair.absorption = Absorption::Color(Arc::new(color));
+        hair
+    }
+
+    // Roughness along the fibre and around it, both in [0, 1].
+    pub fn with_roughness(mut self, longitudinal: f64, azimuthal: f64) -> Self {
+        self.beta_m = longitudinal.clamp(0.01, 1.0);
+        self.beta_n = azimuthal.clamp(0.01, 1.0);
+        self
+    }
+
+    pub fn with_scale_angle(mut self, degrees: f64) -> Self {
+        self.alpha = degrees;
+        self
+    }
+
+    pub fn with_ior(mut self, eta: f64) -> Self {
+        self.eta = eta;
+        self
+    }
+
+    // Frame with x along the fibre and z along the normal, and the lobes at `rec`.
+    fn bsdf(&self, rec: &HitRecord) -> (Onb, HairBsdf) {
+        let n = cross(rec.dpdu, rec.dpdv);
+        let n = if n.length_squared() > 0.0 {
+            n
+        } else {
+            rec.normal
+        };
+        let frame = Onb::build_from_w_and_tangent(n, rec.dpdu);
+
+        let sigma_a = match &self.absorption {
+            Absorption::Coefficient(sigma_a) => *sigma_a,
+            Absorption::Color(color) => {
+                sigma_a_from_color(color.value(rec.u, rec.v, rec.p), self.beta_n)
+            }
+        };
+        (
+            frame,
+            HairBsdf::new(
+                (2.0 * rec.v - 1.0).clamp(-1.0, 1.0),
+                self.eta,
+                sigma_a,
+                self.beta_m,
+                self.beta_n,
+                self.alpha,
+            ),
+        )
+    }
+}
+
+impl Material for Hair {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        let (frame, bsdf) = self.bsdf(rec);
+        let wo = frame.to_local(-unit_vector(r_in.direction()));
+        let wi = bsdf.sample(wo);
+        let pdf = bsdf.pdf(wo, wi);
+        if pdf <= 0.0 {
+            return false;
+        }
+        *attenuation = bsdf.f(wo, wi) / pdf;
+        *scattered = Ray::new(rec.p, frame.local(wi));
+        true
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let (frame, bsdf) = self.bsdf(rec);
+        bsdf.f(frame.to_local(wo), frame.to_local(wi))
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let (frame, bsdf) = self.bsdf(rec);
+        bsdf.pdf(frame.to_local(wo), frame.to_local(wi))
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+}
+
+// The lobes at one point across a fibre. Directions are local, with x along the fibre.
+struct HairBsdf {
+    // Offset across the fibre, from -1 to 1.
+    h: f64,
+    gamma_o: f64,
+    eta: f64,
+    sigma_a: Color,
+    // Longitudinal variance of each order.
+    v: [f64; P_MAX + 1],
+    // Azimuthal logistic scale.
+    s: f64,
+    // Sines and cosines of twice, four and eight times the scale angle.
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+}
+
+impl HairBsdf {
+    fn new(h: f64, eta: f64, sigma_a: Color, beta_m: f64, beta_n: f64, alpha: f64) -> Self {
+        let v0 = (0.726 * beta_m + 0.812 * beta_m.powi(2) + 3.7 * beta_m.powi(20)).powi(2);
+        let s =
+            (PI / 8.0).sqrt() * (0.265 * beta_n + 1.194 * beta_n.powi(2) + 5.372 * beta_n.powi(22));
+
+        let mut sin_2k_alpha = [alpha.to_radians().sin(), 0.0, 0.0];
+        let mut cos_2k_alpha = [safe_sqrt(1.0 - sin_2k_alpha[0].powi(2)), 0.0, 0.0];
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1].powi(2) - sin_2k_alpha[i - 1].powi(2);
+        }
+
+        Self {
+            h,
+            gamma_o: h.asin(),
+            eta,
+            sigma_a,
+            v: [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0],
+            s,
+            sin_2k_alpha,
+            cos_2k_alpha,
+        }
+    }
+
+    // Angle of refraction across the fibre and transmittance of one pass through it, for
+    // light leaving at `sin_theta_o` along it.
+    fn refraction(&self, sin_theta_o: f64, cos_theta_o: f64) -> (f64, Color) {
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = safe_sqrt(1.0 - sin_theta_t * sin_theta_t);
+        // Index of the fibre's cross-section, seen at that angle.
+        let etap = safe_sqrt(self.eta * self.eta - sin_theta_o * sin_theta_o) / cos_theta_o;
+        let sin_gamma_t = (self.h / etap).clamp(-1.0, 1.0);
+        let cos_gamma_t = safe_sqrt(1.0 - sin_gamma_t * sin_gamma_t);
+        let transmittance = (-self.sigma_a * (2.0 * cos_gamma_t / cos_theta_t)).exp();
+        (sin_gamma_t.asin(), transmittance)
+    }
+
+    // Attenuation of each scattering order.
+    fn ap(&self, cos_theta_o: f64, t: Color) -> [Color; P_MAX + 1] {
+        let cos_gamma_o = safe_sqrt(1.0 - self.h * self.h);
+        let f = fresnel_dielectric(cos_theta_o * cos_gamma_o, self.eta);
+        let mut ap = [Color::new(f, f, f); P_MAX + 1];
+        ap[1] = (1.0 - f) * (1.0 - f) * t;
+        for p in 2..P_MAX {
+            ap[p] = ap[p - 1] * t * f;
+        }
+        let tf = f * t;
+        let rest = |i: usize| ap[P_MAX - 1][i] * tf[i] / (1.0 - tf[i]);
+        ap[P_MAX] = Color::new(rest(0), rest(1), rest(2));
+        ap
+    }
+
+    // Probabilities of sampling each order, by the brightness of their attenuations.
+    fn ap_pdf(&self, sin_theta_o: f64, cos_theta_o: f64) -> [f64; P_MAX + 1] {
+        let (_, t) = self.refraction(sin_theta_o, cos_theta_o);
+        let ap = self
+            .ap(cos_theta_o, t)
+            .map(|a| (a.x() + a.y() + a.z()) / 3.0);
+        let sum: f64 = ap.iter().sum();
+        if sum <= 0.0 {
+            return [1.0 / (P_MAX + 1) as f64; P_MAX + 1];
+        }
+        ap.map(|a| a / sum)
+    }
+
+    // The outgoing angle along the fibre, turned by the scales for order `p`.
+    fn tilted(&self, p: usize, sin_theta_o: f64, cos_theta_o: f64) -> (f64, f64) {
+        let (s, c) = (&self.sin_2k_alpha, &self.cos_2k_alpha);
+        let (sin, cos) = match p {
+            0 => (
+                sin_theta_o * c[1] - cos_theta_o * s[1],
+                cos_theta_o * c[1] + sin_theta_o * s[1],
+            ),
+            1 => (
+                sin_theta_o * c[0] + cos_theta_o * s[0],
+                cos_theta_o * c[0] - sin_theta_o * s[0],
+            ),
+            2 => (
+                sin_theta_o * c[2] + cos_theta_o * s[2],
+                cos_theta_o * c[2] - sin_theta_o * s[2],
+            ),
+            _ => (sin_theta_o, cos_theta_o),
+        };
+        (sin, cos.abs())
+    }
+
+    // BSDF times |cos(theta_i)| to the normal.
+    fn f(&self, wo: Vec3, wi: Vec3) -> Color {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i, phi_i) = angles(wi);
+        let (gamma_t, t) = self.refraction(sin_theta_o, cos_theta_o);
+        let ap = self.ap(cos_theta_o, t);
+        let phi = phi_i - phi_o;
+
+        let mut f = Color::new(0.0, 0.0, 0.0);
+        for (p, ap) in ap.iter().enumerate().take(P_MAX) {
+            let (sin_theta_op, cos_theta_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+            let m = mp(
+                cos_theta_i,
+                cos_theta_op,
+                sin_theta_i,
+                sin_theta_op,
+                self.v[p],
+            );
+            f += m * self.np(phi, p, gamma_t) * *ap;
+        }
+        let m = mp(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        );
+        f += m / (2.0 * PI) * ap[P_MAX];
+        f
+    }
+
+    fn pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i, phi_i) = angles(wi);
+        let (gamma_t, _) = self.refraction(sin_theta_o, cos_theta_o);
+        let ap_pdf = self.ap_pdf(sin_theta_o, cos_theta_o);
+        let phi = phi_i - phi_o;
+
+        let mut pdf = 0.0;
+        for (p, ap_pdf) in ap_pdf.iter().enumerate().take(P_MAX) {
+            let (sin_theta_op, cos_theta_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+            let m = mp(
+                cos_theta_i,
+                cos_theta_op,
+                sin_theta_i,
+                sin_theta_op,
+                self.v[p],
+            );
+            pdf += m * ap_pdf * self.np(phi, p, gamma_t);
+        }
+        let m = mp(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        );
+        pdf + m * ap_pdf[P_MAX] / (2.0 * PI)
+    }
+
+    // Picks an order, then an angle along the fibre from its longitudinal lobe and one around
+    // it from its azimuthal lobe.
+    fn sample(&self, wo: Vec3) -> Vec3 {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let ap_pdf = self.ap_pdf(sin_theta_o, cos_theta_o);
+        let mut u = random_f64();
+        let mut p = 0;
+        while p < P_MAX && u >= ap_pdf[p] {
+            u -= ap_pdf[p];
+            p += 1;
+        }
+
+        let (sin_theta_op, cos_theta_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+        let v = self.v[p];
+        let u = random_f64().max(1e-5);
+        let cos_theta = 1.0 + v * (u + (1.0 - u) * (-2.0 / v).exp()).ln();
+        let sin_theta = safe_sqrt(1.0 - cos_theta * cos_theta);
+        let cos_phi = (2.0 * PI * random_f64()).cos();
+        let sin_theta_i = -cos_theta * sin_theta_op + sin_theta * cos_phi * cos_theta_op;
+        let cos_theta_i = safe_sqrt(1.0 - sin_theta_i * sin_theta_i);
+
+        let (gamma_t, _) = self.refraction(sin_theta_o, cos_theta_o);
+        let dphi = if p < P_MAX {
+            exit_azimuth(p, self.gamma_o, gamma_t) + sample_trimmed_logistic(random_f64(), self.s)
+        } else {
+            2.0 * PI * random_f64()
+        };
+        let phi_i = phi_o + dphi;
+        Vec3::new(
+            sin_theta_i,
+            cos_theta_i * phi_i.cos(),
+            cos_theta_i * phi_i.sin(),
+        )
+    }
+
+    // Azimuthal lobe of order `p`, for the difference `phi` of the azimuths.
+    fn np(&self, phi: f64, p: usize, gamma_t: f64) -> f64 {
+        let mut dphi = phi - exit_azimuth(p, self.gamma_o, gamma_t);
+        while dphi > PI {
+            dphi -= 2.0 * PI;
+        }
+        while dphi < -PI {
+            dphi += 2.0 * PI;
+        }
+        trimmed_logistic(dphi, self.s)
+    }
+}
+
+// Sine and cosine of the angle to the normal plane of the fibre, and the azimuth around it.
+fn angles(w: Vec3) -> (f64, f64, f64) {
+    let sin_theta = w.x().clamp(-1.0, 1.0);
+    (
+        sin_theta,
+        safe_sqrt(1.0 - sin_theta * sin_theta),
+        w.z().atan2(w.y()),
+    )
+}
+
+// Longitudinal lobe with variance `v`.
+fn mp(cos_theta_i: f64, cos_theta_o: f64, sin_theta_i: f64, sin_theta_o: f64, v: f64) -> f64 {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    if v <= 0.1 {
+        // In logarithms, which stay finite for narrow lobes.
+        (log_i0(a) - b - 1.0 / v + LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        (-b).exp() * i0(a) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+// Azimuth of the exit of order `p` relative to the entry.
+fn exit_azimuth(p: usize, gamma_o: f64, gamma_t: f64) -> f64 {
+    let p = p as f64;
+    2.0 * p * gamma_t - 2.0 * gamma_o + p * PI
+}
+
+// Modified Bessel function of the first kind and order zero.
+fn i0(x: f64) -> f64 {
+    let mut sum = 0.0;
+    let mut term = 1.0;
+    for i in 1..=10 {
+        sum += term;
+        term *= x * x / (4.0 * (i * i) as f64);
+    }
+    sum
+}
+
+fn log_i0(x: f64) -> f64 {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        i0(x).ln()
+    }
+}
+
+fn logistic(x: f64, s: f64) -> f64 {
+    let e = (-x.abs() / s).exp();
+    e / (s * (1.0 + e) * (1.0 + e))
+}
+
+fn logistic_cdf(x: f64, s: f64) -> f64 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+// The logistic distribution restricted to [-pi, pi].
+fn trimmed_logistic(x: f64, s: f64) -> f64 {
+    logistic(x, s) / (logistic_cdf(PI, s) - logistic_cdf(-PI, s))
+}
+
+fn sample_trimmed_logistic(u: f64, s: f64) -> f64 {
+    let k = logistic_cdf(PI, s) - logistic_cdf(-PI, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(-PI, s)) - 1.0).ln();
+    x.clamp(-PI, PI)
+}
+
+// Absorption that gives hair of roughly `color` after multiple scattering, by the fit of
+// Chiang et al.
+fn sigma_a_from_color(color: Color, beta_n: f64) -> Color {
+    let d = 5.969 - 0.215 * beta_n + 2.532 * beta_n.powi(2) - 10.73 * beta_n.powi(3)
+        + 5.574 * beta_n.powi(4)
+        + 0.245 * beta_n.powi(5);
+    let coefficient = |c: f64| (c.clamp(1e-4, 1.0).ln() / d).powi(2);
+    Color::new(
+        coefficient(color.x()),
+        coefficient(color.y()),
+        coefficient(color.z()),
+    )
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
@@ -1,17 +1,65 @@
 use std::sync::Arc;
 
+use crate::aabb::Aabb;
+use crate::medium_stack::Interior;
 use crate::ray::Ray;
-use crate::vec3::{dot, Point3, Vec3};
+use crate::vec3::{dot, Color, Point3, Vec3};
 
 pub struct HitRecord {
     pub p: Point3,
+    // Shading normal, which materials use. It differs from the true surface normal only on
+    // bump or normal mapped surfaces. Both face the incoming ray.
     pub normal: Vec3,
+    pub geometric_normal: Vec3,
     pub material: Arc<dyn Material>,
     pub t: f64,
+    // Surface coordinates for texture lookups.
+    pub u: f64,
+    pub v: f64,
+    // Derivatives of the position along u and v, zero where a shape has no parameterization.
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
     pub front_face: bool,
+    // Indices of refraction on the side the ray arrived from and on the far side of the
+    // surface. They only differ at the boundary of a dielectric interior.
+    pub ior: f64,
+    pub transmitted_ior: f64,
+    // Absorption along the ray up to this hit.
+    pub transmittance: Color,
+    // Wavelength in nm of the ray that found the hit in spectral mode, so that evaluating the
+    // material for light samples agrees with `scatter`.
+    pub wavelength: Option<f64>,
 }
 
 impl HitRecord {
+    pub fn new(material: Arc<dyn Material>) -> Self {
+        Self {
+            p: Point3::new(0.0, 0.0, 0.0),
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            geometric_normal: Vec3::new(0.0, 0.0, 0.0),
+            material,
+            t: 0.0,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::new(0.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 0.0, 0.0),
+            front_face: false,
+            ior: 1.0,
+            transmitted_ior: 1.0,
+            transmittance: Color::new(1.0, 1.0, 1.0),
+            wavelength: None,
+        }
+    }
+
+    // Clears what shapes fill in, so that one record can be reused across intersection tests.
+    pub fn reset(&mut self) {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        (self.p, self.normal, self.geometric_normal) = (zero, zero, zero);
+        (self.t, self.u, self.v) = (0.0, 0.0, 0.0);
+        (self.dpdu, self.dpdv) = (zero, zero);
+        self.front_face = false;
+    }
+
     pub fn set_face_normal(&mut self, r: Ray, outward_normal: Vec3) {
         self.front_face = dot(r.direction(), outward_normal) < 0.0;
         self.normal = if self.front_face {
@@ -19,6 +67,14 @@ impl HitRecord {
         } else {
             -outward_normal
         };
+        self.geometric_normal = self.normal;
+    }
+
+    // Whether `w` lies on the same side of the surface by the shading and the geometric
+    // normal. Bumped shading normals make some directions look above the surface that are
+    // really below it; following those lets light leak through.
+    pub fn is_consistent(&self, w: Vec3) -> bool {
+        dot(w, self.normal) * dot(w, self.geometric_normal) >= 0.0
     }
 }
 
@@ -31,13 +87,80 @@ impl Hittable {
     }
 }
 
+#[derive(Clone)]
 pub struct Hittable {
     pub shape: Arc<dyn Shape>,
     pub material: Arc<dyn Material>,
 }
 
+// Shadow rays ignore intersections closer than this to their origin.
+pub const SHADOW_EPSILON: f64 = 0.001;
+
+// Solid angle density at the origin of `r` of heading along it towards a point sampled
+// uniformly on a surface of `area`. `next_hit` gives the ray parameter and normal of the first
+// intersection beyond a parameter; every intersection counts, as the points behind the
+// nearest could have been sampled too.
+pub fn area_pdf<F: Fn(f64) -> Option<(f64, Vec3)>>(r: &Ray, area: f64, next_hit: F) -> f64 {
+    if area <= 0.0 {
+        return 0.0;
+    }
+    let direction = r.direction();
+    let mut pdf = 0.0;
+    let mut t_min = SHADOW_EPSILON;
+    while let Some((t, n)) = next_hit(t_min) {
+        let cosine = dot(direction, n).abs() / direction.length();
+        if cosine > 0.0 {
+            pdf += t * t * direction.length_squared() / (cosine * area);
+        }
+        t_min = t + SHADOW_EPSILON;
+    }
+    pdf
+}
+
 pub trait Shape: Send + Sync {
     fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
+
+    // Whether anything lies on `r` between `SHADOW_EPSILON` and `t_max`. Returns on the first
+    // intersection found, without filling a `HitRecord`.
+    fn occluded(&self, r: &Ray, t_max: f64) -> bool;
+
+    // Fraction of light passing along `r` between `SHADOW_EPSILON` and `t_max`. Only
+    // participating media are partially transparent.
+    fn transmittance(&self, r: &Ray, t_max: f64) -> f64 {
+        if self.occluded(r, t_max) {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    // Solid angle density of `random` as seen from `origin`.
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    fn random(&self, _origin: Point3) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    fn area(&self) -> f64 {
+        0.0
+    }
+
+    // Uniformly samples a point on the surface by area, with its outward normal.
+    fn sample_area(&self) -> Option<(Point3, Vec3)> {
+        None
+    }
+
+    // None for shapes without finite bounds.
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    // Whether hits may have a shading normal that differs from the geometric one.
+    fn perturbs_normals(&self) -> bool {
+        false
+    }
 }
 
 pub trait Material: Send + Sync {
@@ -48,4 +171,42 @@ pub trait Material: Send + Sync {
         attenuation: &mut Vec3,
         scattered: &mut Ray,
     ) -> bool;
+
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // BSDF times |cos(theta_i)| for unit directions `wo` (towards the viewer) and `wi`.
+    fn eval(&self, _rec: &HitRecord, _wo: Vec3, _wi: Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // Solid angle density with which `scatter` produces `wi` given `wo`.
+    fn pdf(&self, _rec: &HitRecord, _wo: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    // Materials that only scatter into discrete directions cannot be evaluated or light sampled.
+    fn is_specular(&self) -> bool {
+        true
+    }
+
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+
+    // The medium enclosed by surfaces of this material, for materials that bound one.
+    fn interior(&self, _wavelength: Option<f64>) -> Option<Interior> {
+        None
+    }
+
+    // Materials standing in for one of several others pick it here, once per hit, so that
+    // every later query at the hit sees the same material.
+    fn choose(&self, _rec: &HitRecord) -> Option<Arc<dyn Material>> {
+        None
+    }
 }
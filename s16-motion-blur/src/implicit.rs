@@ -0,0 +1,120 @@
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Shape, SHADOW_EPSILON},
+    polynomial::SturmSequence,
+    ray::Ray,
+    vec3::{unit_vector, Point3, Vec3},
+};
+
+// The surface f(x, y, z) = 0 of a polynomial inside `bounds`, given as terms (c, [i, j, k])
+// for c x^i y^j z^k. Along a ray f becomes a polynomial in t, whose nearest root Sturm
+// sequences find however close the roots are. The outside is where f is positive. As with
+// quadrics there is no parameterization, so u and v are zero.
+pub struct Implicit {
+    terms: Vec<(f64, [u32; 3])>,
+    bounds: Aabb,
+}
+
+impl Implicit {
+    pub fn new(terms: Vec<(f64, [u32; 3])>, bounds: Aabb) -> Self {
+        Self { terms, bounds }
+    }
+
+    // Ray parameter of the nearest intersection with `r` inside the bounds.
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+        let (t_enter, t_exit) = self.bounds.interval(r, t_min, t_max)?;
+        // Expanding about where the ray enters the bounds keeps the coefficients small.
+        let o = r.at(t_enter);
+        let d = r.direction();
+        let degree = self
+            .terms
+            .iter()
+            .map(|(_, [i, j, k])| (i + j + k) as usize)
+            .max()?;
+
+        // Powers of each coordinate along the ray, (o + t d)^n as polynomials in t.
+        let powers: Vec<Vec<Vec<f64>>> = (0..3)
+            .map(|axis| {
+                let linear = [o[axis], d[axis]];
+                let mut powers = vec![vec![1.0]];
+                for n in 0..degree {
+                    let next = multiply(&powers[n], &linear);
+                    powers.push(next);
+                }
+                powers
+            })
+            .collect();
+
+        let mut c = vec![0.0; degree + 1];
+        for &(coefficient, [i, j, k]) in self.terms.iter() {
+            let xy = multiply(&powers[0][i as usize], &powers[1][j as usize]);
+            let term = multiply(&xy, &powers[2][k as usize]);
+            for (ci, ti) in c.iter_mut().zip(term.iter()) {
+                *ci += coefficient * ti;
+            }
+        }
+        SturmSequence::new(&c)
+            .first_root(0.0, t_exit - t_enter)
+            .map(|t| t + t_enter)
+    }
+
+    fn gradient(&self, p: Point3) -> Vec3 {
+        let mut gradient = Vec3::new(0.0, 0.0, 0.0);
+        for &(coefficient, exponents) in self.terms.iter() {
+            for axis in 0..3 {
+                if exponents[axis] == 0 {
+                    continue;
+                }
+                let mut derivative = coefficient * exponents[axis] as f64;
+                for (other, &n) in exponents.iter().enumerate() {
+                    let n = if other == axis { n - 1 } else { n };
+                    derivative *= p[other].powi(n as i32);
+                }
+                gradient[axis] += derivative;
+            }
+        }
+        gradient
+    }
+}
+
+impl Shape for Implicit {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let t = match self.intersect(&r, t_min, t_max) {
+            Some(t) => t,
+            None => return false,
+        };
+        rec.t = t;
+        rec.p = r.at(t);
+        // The gradient vanishes at singular points, where any normal will do.
+        let gradient = self.gradient(rec.p);
+        let normal = if gradient.length_squared() > 0.0 {
+            unit_vector(gradient)
+        } else {
+            -unit_vector(r.direction())
+        };
+        rec.set_face_normal(r, normal);
+        rec.u = 0.0;
+        rec.v = 0.0;
+        rec.dpdu = Vec3::new(0.0, 0.0, 0.0);
+        rec.dpdv = Vec3::new(0.0, 0.0, 0.0);
+        true
+    }
+
+    fn occluded(&self, r: &Ray, t_max: f64) -> bool {
+        self.intersect(r, SHADOW_EPSILON, t_max).is_some()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+fn multiply(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut product = vec![0.0; a.len() + b.len() - 1];
+    for (i, ai) in a.iter().enumerate() {
+        for (j, bj) in b.iter().enumerate() {
+            product[i + j] += ai * bj;
+        }
+    }
+    product
+}
@@ -0,0 +1,281 @@
+use std::sync::Arc;
+
+use crate::{
+    bdpt::ray_color_bdpt,
+    camera::Camera,
+    film::Film,
+    hittable::HitRecord,
+    medium_stack::{HitMedia, MediumStack},
+    photon::{ray_color_photon, PhotonMaps},
+    ray::Ray,
+    scene::Scene,
+    spectrum::{SampledSpectrum, SampledWavelengths},
+    util::random_f64,
+    vec3::{unit_vector, Color, Point3, Vec3},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Integrator {
+    PathTracer,
+    Spectral,
+    Mis,
+    Bdpt,
+    // Progressive photon mapping: every pass traces `photons_per_pass` new photons and gathers
+    // them with a radius that shrinks from `initial_radius` at a rate controlled by `alpha`.
+    PhotonMapping {
+        photons_per_pass: usize,
+        initial_radius: f64,
+        alpha: f64,
+    },
+}
+
+// Per-pass data shared by every pixel sample of that pass.
+#[derive(Default)]
+pub struct PassState {
+    photon_maps: Option<PhotonMaps>,
+}
+
+impl Integrator {
+    pub fn begin_pass(&self, scene: &Scene, pass: usize, max_depth: i32) -> PassState {
+        match *self {
+            Integrator::PhotonMapping {
+                photons_per_pass,
+                initial_radius,
+                alpha,
+            } => {
+                let radius = PhotonMaps::radius_for_pass(initial_radius, alpha, pass);
+                PassState {
+                    photon_maps: Some(PhotonMaps::build(
+                        scene,
+                        photons_per_pass,
+                        radius,
+                        max_depth,
+                    )),
+                }
+            }
+            _ => PassState::default(),
+        }
+    }
+
+    // `film` receives contributions that do not belong to the pixel `r` was traced for.
+    pub fn li(
+        &self,
+        r: Ray,
+        scene: &Scene,
+        camera: &Camera,
+        film: &Film,
+        pass: &PassState,
+        max_depth: i32,
+    ) -> Color {
+        match self {
+            Integrator::PathTracer => ray_color(r, &MediumStack::new(), scene, max_depth),
+            Integrator::Spectral => {
+                let mut lambdas = SampledWavelengths::sample_uniform(random_f64());
+                let l = ray_color_spectral(r, scene, &mut lambdas, max_depth);
+                lambdas.to_rgb(l)
+            }
+            Integrator::Mis => ray_color_mis(r, scene, max_depth),
+            Integrator::Bdpt => ray_color_bdpt(r, scene, camera, film, max_depth),
+            Integrator::PhotonMapping { .. } => match &pass.photon_maps {
+                Some(maps) => ray_color_photon(r, scene, maps, max_depth),
+                None => Color::new(0.0, 0.0, 0.0),
+            },
+        }
+    }
+}
+
+// `media` are the dielectrics `r` starts inside of.
+pub fn ray_color(r: Ray, media: &MediumStack, scene: &Scene, depth: i32) -> Color {
+    if depth <= 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    if let Some((rec, hit_media)) = scene.hit(r, media, 0.001, f64::INFINITY) {
+        let mut scattered = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        let mut attenuation = Color::new(0.0, 0.0, 0.0);
+        let wo = -unit_vector(r.direction());
+        let emitted = rec.material.emitted(&rec) + delta_lighting(scene, &rec, &hit_media, wo);
+
+        if Arc::clone(&rec.material).scatter(r, &rec, &mut attenuation, &mut scattered) {
+            let media = hit_media.leaving(rec.normal, scattered.direction());
+            return rec.transmittance
+                * (emitted + attenuation * ray_color(scattered, media, scene, depth - 1));
+        }
+        return rec.transmittance * emitted;
+    }
+
+    scene.escaped(r)
+}
+
+// Spectral variant of `ray_color`: the ray carries the hero wavelength so that dispersive
+// materials can pick their IOR, and RGB attenuation and radiance are upsampled per wavelength.
+pub fn ray_color_spectral(
+    r: Ray,
+    scene: &Scene,
+    lambdas: &mut SampledWavelengths,
+    depth: i32,
+) -> SampledSpectrum {
+    let mut l = SampledSpectrum::splat(0.0);
+    let mut throughput = SampledSpectrum::splat(1.0);
+    let mut ray = r.with_wavelength(lambdas.hero());
+    let mut media = MediumStack::new();
+
+    for _ in 0..depth {
+        let (rec, hit_media) = match scene.hit(ray, &media, 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => {
+                let background = scene.escaped(ray);
+                return l + throughput * SampledSpectrum::from_rgb(background, lambdas);
+            }
+        };
+
+        if rec.material.is_dispersive() {
+            lambdas.terminate_secondary();
+        }
+        throughput *= SampledSpectrum::from_rgb(rec.transmittance, lambdas);
+        let wo = -unit_vector(ray.direction());
+        let emitted = rec.material.emitted(&rec) + delta_lighting(scene, &rec, &hit_media, wo);
+        l += throughput * SampledSpectrum::from_rgb(emitted, lambdas);
+
+        let mut scattered = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        let mut attenuation = Color::new(0.0, 0.0, 0.0);
+        if !Arc::clone(&rec.material).scatter(ray, &rec, &mut attenuation, &mut scattered) {
+            break;
+        }
+
+        throughput *= SampledSpectrum::from_rgb(attenuation, lambdas);
+        if throughput.is_black() {
+            break;
+        }
+        ray = scattered.with_wavelength(lambdas.hero());
+        media = *hit_media.leaving(rec.normal, scattered.direction());
+    }
+
+    l
+}
+
+// Delta lights are never hit by scattered rays, so the integrators without light sampling
+// add them with one shadow ray per light at every vertex.
+pub fn delta_lighting(scene: &Scene, rec: &HitRecord, hit_media: &HitMedia, wo: Vec3) -> Color {
+    let mut l = Color::new(0.0, 0.0, 0.0);
+    if rec.material.is_specular() {
+        return l;
+    }
+    for light in scene.lights.iter().filter(|light| light.is_delta()) {
+        if let Some(ls) = light.sample_li(rec.p) {
+            let f = rec.material.eval(rec, wo, ls.wi);
+            if f.length_squared() > 0.0 {
+                let tr = scene.segment_transmittance(rec.p, ls.wi, ls.distance)
+                    * hit_media
+                        .leaving(rec.normal, ls.wi)
+                        .transmittance(ls.distance);
+                l += f * ls.radiance * tr / ls.pdf;
+            }
+        }
+    }
+    l
+}
+
+pub fn power_heuristic(f_pdf: f64, g_pdf: f64) -> f64 {
+    let f2 = f_pdf * f_pdf;
+    let g2 = g_pdf * g_pdf;
+    if f2 + g2 == 0.0 {
+        return 0.0;
+    }
+    f2 / (f2 + g2)
+}
+
+// Path tracer that combines one light sample and one BSDF sample per non-specular vertex
+// with the power heuristic. Emission found by BSDF sampling is weighted against the light
+// density of the previous vertex.
+pub fn ray_color_mis(r: Ray, scene: &Scene, max_depth: i32) -> Color {
+    let mut l = Color::new(0.0, 0.0, 0.0);
+    let mut beta = Color::new(1.0, 1.0, 1.0);
+    let mut ray = r;
+    let mut specular_bounce = true;
+    let mut prev_p = r.origin();
+    let mut prev_n = Vec3::new(0.0, 0.0, 0.0);
+    let mut prev_bsdf_pdf = 0.0;
+    let mut media = MediumStack::new();
+
+    for _ in 0..max_depth {
+        let (object, rec, hit_media) = match scene.hit_object(ray, &media, 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => {
+                l += beta * scene.background.value(ray);
+                let wi = unit_vector(ray.direction());
+                for &light in scene.infinite_lights() {
+                    let le = scene.lights[light].le(&ray);
+                    if specular_bounce {
+                        l += beta * le;
+                    } else if le.length_squared() > 0.0 {
+                        let light_pdf = scene.light_pdf(prev_p, prev_n, light, wi);
+                        l += beta * le * power_heuristic(prev_bsdf_pdf, light_pdf);
+                    }
+                }
+                break;
+            }
+        };
+
+        beta = beta * rec.transmittance;
+        let emitted = rec.material.emitted(&rec);
+        if specular_bounce {
+            l += beta * emitted;
+        } else if emitted.length_squared() > 0.0 {
+            let light_pdf = scene.area_light(object).map_or(0.0, |light| {
+                scene.light_pdf(prev_p, prev_n, light, unit_vector(ray.direction()))
+            });
+            l += beta * emitted * power_heuristic(prev_bsdf_pdf, light_pdf);
+        }
+
+        let wo = -unit_vector(ray.direction());
+        let material = Arc::clone(&rec.material);
+
+        let sampled_light = if material.is_specular() {
+            None
+        } else {
+            scene.sample_light(rec.p, rec.normal)
+        };
+        if let Some((light_index, light_pmf)) = sampled_light {
+            let light = &scene.lights[light_index];
+            if let Some(ls) = light.sample_li(rec.p) {
+                let f = material.eval(&rec, wo, ls.wi);
+                let tr = if f.length_squared() > 0.0 && ls.radiance.length_squared() > 0.0 {
+                    scene.segment_transmittance(rec.p, ls.wi, ls.distance)
+                } else {
+                    0.0
+                };
+                if tr > 0.0 {
+                    let light_pdf = ls.pdf * light_pmf;
+                    let weight = if light.is_delta() {
+                        1.0
+                    } else {
+                        power_heuristic(light_pdf, material.pdf(&rec, wo, ls.wi))
+                    };
+                    let absorption = hit_media
+                        .leaving(rec.normal, ls.wi)
+                        .transmittance(ls.distance);
+                    l += beta * f * ls.radiance * absorption * (tr * weight / light_pdf);
+                }
+            }
+        }
+
+        let mut scattered = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        let mut attenuation = Color::new(0.0, 0.0, 0.0);
+        if !material.scatter(ray, &rec, &mut attenuation, &mut scattered) {
+            break;
+        }
+
+        specular_bounce = material.is_specular();
+        if !specular_bounce {
+            prev_bsdf_pdf = material.pdf(&rec, wo, unit_vector(scattered.direction()));
+        }
+        prev_p = rec.p;
+        prev_n = rec.normal;
+        beta = beta * attenuation;
+        ray = scattered;
+        media = *hit_media.leaving(rec.normal, scattered.direction());
+    }
+
+    l
+}
@@ -0,0 +1,100 @@
+use crate::vec3::Point3;
+
+// Balanced 3-d tree stored implicitly: the median of every slice is its node, the halves on
+// either side are its subtrees.
+pub struct KdTree<T> {
+    items: Vec<(Point3, T)>,
+    axes: Vec<u8>,
+}
+
+impl<T> KdTree<T> {
+    pub fn build(mut items: Vec<(Point3, T)>) -> Self {
+        let mut axes = vec![0; items.len()];
+        build_recursive(&mut items, &mut axes);
+        Self { items, axes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    // Calls `f` for every item within `radius` of `p`, with its squared distance.
+    pub fn for_each_within<F: FnMut(&T, f64)>(&self, p: Point3, radius: f64, mut f: F) {
+        query_recursive(&self.items, &self.axes, p, radius * radius, &mut f);
+    }
+}
+
+fn build_recursive<T>(items: &mut [(Point3, T)], axes: &mut [u8]) {
+    if items.len() <= 1 {
+        return;
+    }
+
+    let mut min = [f64::INFINITY; 3];
+    let mut max = [f64::NEG_INFINITY; 3];
+    for (p, _) in items.iter() {
+        for a in 0..3 {
+            min[a] = min[a].min(p[a]);
+            max[a] = max[a].max(p[a]);
+        }
+    }
+    let axis = (0..3)
+        .max_by(|&a, &b| (max[a] - min[a]).total_cmp(&(max[b] - min[b])))
+        .unwrap();
+
+    let mid = items.len() / 2;
+    items.select_nth_unstable_by(mid, |a, b| a.0[axis].total_cmp(&b.0[axis]));
+    axes[mid] = axis as u8;
+
+    let (left_items, rest) = items.split_at_mut(mid);
+    let (left_axes, rest_axes) = axes.split_at_mut(mid);
+    build_recursive(left_items, left_axes);
+    build_recursive(&mut rest[1..], &mut rest_axes[1..]);
+}
+
+fn query_recursive<T, F: FnMut(&T, f64)>(
+    items: &[(Point3, T)],
+    axes: &[u8],
+    p: Point3,
+    radius_squared: f64,
+    f: &mut F,
+) {
+    if items.is_empty() {
+        return;
+    }
+
+    let mid = items.len() / 2;
+    let (q, item) = &items[mid];
+    let distance_squared = (*q - p).length_squared();
+    if distance_squared <= radius_squared {
+        f(item, distance_squared);
+    }
+    if items.len() == 1 {
+        return;
+    }
+
+    let axis = axes[mid] as usize;
+    let d = p[axis] - q[axis];
+    let (near, near_axes, far, far_axes) = if d <= 0.0 {
+        (
+            &items[..mid],
+            &axes[..mid],
+            &items[mid + 1..],
+            &axes[mid + 1..],
+        )
+    } else {
+        (
+            &items[mid + 1..],
+            &axes[mid + 1..],
+            &items[..mid],
+            &axes[..mid],
+        )
+    };
+    query_recursive(near, near_axes, p, radius_squared, f);
+    if d * d <= radius_squared {
+        query_recursive(far, far_axes, p, radius_squared, f);
+    }
+}
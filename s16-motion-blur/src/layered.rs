@@ -0,0 +1,491 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    hittable::{HitRecord, Material},
+    integrator::power_heuristic,
+    microfacet::{fresnel_dielectric, reflect_local, refract_local, TrowbridgeReitz},
+    onb::Onb,
+    ray::Ray,
+    util::random_f64,
+    vec3::{dot, unit_vector, Color, Point3, Vec3},
+};
+
+// Dielectric coating over any base material, such as clearcoated car paint or varnished wood,
+// after pbrt-v4's LayeredBxDF (Guo et al., "Position-Free Monte Carlo Simulation for
+// Arbitrary Layered BSDFs"). Light refracts into the coating, bounces between the base and the
+// underside of the interface, and is attenuated by the coating's absorption on every crossing.
+//
+// `scatter` follows one such random walk exactly. `eval` and `pdf` are unbiased stochastic
+// estimates from a single walk each, so repeated calls return different values. The base is
+// treated as opaque: light it transmits is lost.
+pub struct Coated {
+    base: Arc<dyn Material>,
+    ior: f64,
+    distribution: TrowbridgeReitz,
+    absorption: Color,
+    thickness: f64,
+}
+
+// Bounces inside the coating before a walk is given up.
+const MAX_DEPTH: usize = 100;
+
+impl Coated {
+    // A roughness of zero gives a near-mirror coat rather than a perfect one, so that the base
+    // can still be light sampled through it.
+    pub fn new(base: Arc<dyn Material>, ior: f64, roughness: f64) -> Self {
+        Self {
+            base,
+            ior,
+            distribution: TrowbridgeReitz::new((roughness * roughness).max(1e-3)),
+            absorption: Color::new(0.0, 0.0, 0.0),
+            thickness: 0.0,
+        }
+    }
+
+    // Tints the coating with `absorption` per unit length through a layer `thickness` thick.
+    pub fn with_absorption(mut self, absorption: Color, thickness: f64) -> Self {
+        self.absorption = absorption;
+        self.thickness = thickness;
+        self
+    }
+
+    fn interface(&self, rec: &HitRecord) -> Interface {
+        Interface {
+            eta: self.ior / rec.ior,
+            distribution: self.distribution,
+        }
+    }
+
+    // Attenuation of a straight crossing of the coating in local direction `w`.
+    fn tr(&self, w: Vec3) -> Color {
+        if self.thickness <= 0.0 || self.absorption.length_squared() == 0.0 {
+            return Color::new(1.0, 1.0, 1.0);
+        }
+        if w.z() == 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        (-(self.thickness / w.z().abs()) * self.absorption).exp()
+    }
+
+    // Samples the base for light travelling in local direction `w`, returning the scattered
+    // direction, the weight f * cos / pdf and the pdf.
+    fn sample_base(&self, rec: &HitRecord, uvw: &Onb, w: Vec3) -> Option<(Vec3, Color, f64)> {
+        let mut attenuation = Color::new(0.0, 0.0, 0.0);
+        let mut scattered = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        let r_in = Ray::new(rec.p, uvw.local(w));
+        if !self
+            .base
+            .scatter(r_in, rec, &mut attenuation, &mut scattered)
+        {
+            return None;
+        }
+        if scattered.direction().length_squared() == 0.0 {
+            return None;
+        }
+        let wi = uvw.to_local(unit_vector(scattered.direction()));
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        let pdf = if self.base.is_specular() {
+            0.0
+        } else {
+            self.base.pdf(rec, uvw.local(-w), uvw.local(wi))
+        };
+        Some((wi, attenuation, pdf))
+    }
+
+    fn base_eval(&self, rec: &HitRecord, uvw: &Onb, wo: Vec3, wi: Vec3) -> Color {
+        self.base.eval(rec, uvw.local(wo), uvw.local(wi))
+    }
+
+    fn base_pdf(&self, rec: &HitRecord, uvw: &Onb, wo: Vec3, wi: Vec3) -> f64 {
+        self.base.pdf(rec, uvw.local(wo), uvw.local(wi))
+    }
+}
+
+impl Material for Coated {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-unit_vector(r_in.direction()));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+        let top = self.interface(rec);
+
+        let bs = match top.sample(wo, Mode::Radiance, Lobes::All) {
+            Some(bs) => bs,
+            None => return false,
+        };
+        let mut beta = bs.weight();
+        let mut w = bs.wi;
+        if w.z() > 0.0 {
+            *attenuation = beta;
+            *scattered = Ray::new(rec.p, uvw.local(w));
+            return true;
+        }
+
+        let mut at_top = true;
+        for depth in 0..MAX_DEPTH {
+            if !russian_roulette(depth, &mut beta) {
+                return false;
+            }
+            beta = beta * self.tr(w);
+            at_top = !at_top;
+
+            if at_top {
+                let bs = match top.sample(-w, Mode::Radiance, Lobes::All) {
+                    Some(bs) => bs,
+                    None => return false,
+                };
+                beta = beta * bs.weight();
+                w = bs.wi;
+                if w.z() > 0.0 {
+                    *attenuation = beta;
+                    *scattered = Ray::new(rec.p, uvw.local(w));
+                    return true;
+                }
+            } else {
+                let (wi, weight, _) = match self.sample_base(rec, &uvw, w) {
+                    Some(sampled) => sampled,
+                    None => return false,
+                };
+                beta = beta * weight;
+                w = wi;
+            }
+        }
+        false
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let uvw = Onb::build_from_w(rec.normal);
+        let (wo, wi) = (uvw.to_local(wo), uvw.to_local(wi));
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let top = self.interface(rec);
+        let top_f = top.f(wo, wi, Mode::Radiance);
+        let mut f = Color::new(top_f, top_f, top_f);
+
+        // Paths entering from `wo`, walking inside, and leaving towards `wi`. The exit is
+        // connected both from the base and by sampling the interface, combined with MIS.
+        let wos = top.sample(wo, Mode::Radiance, Lobes::Transmission);
+        let wis = top.sample(wi, Mode::Importance, Lobes::Transmission);
+        let (wos, wis) = match (wos, wis) {
+            (Some(wos), Some(wis)) => (wos, wis),
+            _ => return f * wi.z(),
+        };
+
+        let mut beta = wos.weight();
+        let mut w = wos.wi;
+        let mut at_top = true;
+        for depth in 0..MAX_DEPTH {
+            if !russian_roulette(depth, &mut beta) {
+                break;
+            }
+            beta = beta * self.tr(w);
+            at_top = !at_top;
+
+            if at_top {
+                let bs = match top.sample(-w, Mode::Radiance, Lobes::Reflection) {
+                    Some(bs) => bs,
+                    None => break,
+                };
+                beta = beta * bs.weight();
+                w = bs.wi;
+                continue;
+            }
+
+            if !self.base.is_specular() {
+                let wt = if top.is_smooth() {
+                    1.0
+                } else {
+                    power_heuristic(wis.pdf, self.base_pdf(rec, &uvw, -w, -wis.wi))
+                };
+                f += beta
+                    * self.base_eval(rec, &uvw, -w, -wis.wi)
+                    * self.tr(wis.wi)
+                    * (wt * wis.f / wis.pdf);
+            }
+
+            let (wi_base, weight, pdf_base) = match self.sample_base(rec, &uvw, w) {
+                Some(sampled) => sampled,
+                None => break,
+            };
+            beta = beta * weight;
+            w = wi_base;
+
+            if !top.is_smooth() {
+                let f_exit = top.f(-w, wi, Mode::Radiance);
+                if f_exit > 0.0 {
+                    let wt = if self.base.is_specular() {
+                        1.0
+                    } else {
+                        // The density of the base connection producing `w`.
+                        power_heuristic(pdf_base, top.pdf(wi, -w, Lobes::Transmission))
+                    };
+                    f += beta * self.tr(w) * (f_exit * wt);
+                }
+            }
+        }
+
+        f * wi.z()
+    }
+
+    // Approximates the density of `scatter` with its single-bounce paths, mixed with a
+    // uniform density so that no direction the walk can reach gets a zero pdf. Only used to
+    // weight light and BSDF samples, so it need not integrate to one exactly.
+    fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let uvw = Onb::build_from_w(rec.normal);
+        let (wo, wi) = (uvw.to_local(wo), uvw.to_local(wi));
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let top = self.interface(rec);
+        let mut pdf = top.pdf(wo, wi, Lobes::All);
+
+        let wos = top.sample(wo, Mode::Radiance, Lobes::Transmission);
+        let wis = top.sample(wi, Mode::Importance, Lobes::Transmission);
+        if let (Some(wos), Some(wis)) = (wos, wis) {
+            let mut transmitted = 0.0;
+            if top.is_smooth() {
+                transmitted += self.base_pdf(rec, &uvw, -wos.wi, -wis.wi);
+            } else if let Some((wi_base, _, pdf_base)) = self.sample_base(rec, &uvw, wos.wi) {
+                if self.base.is_specular() {
+                    transmitted += top.pdf(-wi_base, wi, Lobes::All);
+                } else {
+                    let r_pdf = self.base_pdf(rec, &uvw, -wos.wi, -wis.wi);
+                    transmitted += power_heuristic(wis.pdf, r_pdf) * r_pdf;
+                    let t_pdf = top.pdf(-wi_base, wi, Lobes::All);
+                    transmitted += power_heuristic(pdf_base, t_pdf) * t_pdf;
+                }
+            }
+            // Weighted by how often the walk enters the coating at all.
+            pdf += (1.0 - fresnel_dielectric(wo.z(), top.eta)) * transmitted;
+        }
+
+        0.1 / (4.0 * PI) + 0.9 * pdf
+    }
+
+    fn is_specular(&self) -> bool {
+        self.distribution.effectively_smooth() && self.base.is_specular()
+    }
+}
+
+// Continues walks with little throughput left only with a probability, returning whether
+// the walk survives.
+fn russian_roulette(depth: usize, beta: &mut Color) -> bool {
+    let max = beta.x().max(beta.y()).max(beta.z());
+    if depth > 3 && max < 0.25 {
+        let q = (1.0 - max).max(0.0);
+        if random_f64() < q {
+            return false;
+        }
+        *beta /= 1.0 - q;
+    }
+    true
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    // Transport of radiance, towards the camera.
+    Radiance,
+    // Transport of importance, towards the lights.
+    Importance,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Lobes {
+    All,
+    Reflection,
+    Transmission,
+}
+
+impl Lobes {
+    // Probabilities of sampling reflection and transmission, given their Fresnel weights.
+    fn split(self, r: f64, t: f64) -> Option<(f64, f64)> {
+        let (pr, pt) = match self {
+            Lobes::All => (r, t),
+            Lobes::Reflection => (r, 0.0),
+            Lobes::Transmission => (0.0, t),
+        };
+        if pr + pt <= 0.0 {
+            return None;
+        }
+        Some((pr / (pr + pt), pt / (pr + pt)))
+    }
+}
+
+struct InterfaceSample {
+    wi: Vec3,
+    // BSDF value and its density; for smooth interfaces, relative to the delta distribution.
+    f: f64,
+    pdf: f64,
+}
+
+impl InterfaceSample {
+    fn weight(&self) -> Color {
+        let w = self.f * self.wi.z().abs() / self.pdf;
+        Color::new(w, w, w)
+    }
+}
+
+// The dielectric interface on top of the coating in the local frame, with the outside above
+// and the coating below. Unlike the closed dielectrics, light entering the coating leaves it
+// through the same interface, so radiance is scaled by 1 / eta^2 on refraction.
+struct Interface {
+    // n_coating / n_outside.
+    eta: f64,
+    distribution: TrowbridgeReitz,
+}
+
+impl Interface {
+    fn is_smooth(&self) -> bool {
+        self.eta == 1.0 || self.distribution.effectively_smooth()
+    }
+
+    // n_t / n_i for light leaving along `wo`.
+    fn etap(&self, wo: Vec3) -> f64 {
+        if wo.z() > 0.0 {
+            self.eta
+        } else {
+            1.0 / self.eta
+        }
+    }
+
+    fn sample(&self, wo: Vec3, mode: Mode, lobes: Lobes) -> Option<InterfaceSample> {
+        if wo.z() == 0.0 {
+            return None;
+        }
+        let etap = self.etap(wo);
+        let scale = if mode == Mode::Radiance {
+            1.0 / (etap * etap)
+        } else {
+            1.0
+        };
+
+        if self.is_smooth() {
+            let r = fresnel_dielectric(wo.z(), self.eta);
+            let (pr, pt) = lobes.split(r, 1.0 - r)?;
+            if random_f64() < pr {
+                let wi = Vec3::new(-wo.x(), -wo.y(), wo.z());
+                return Some(InterfaceSample {
+                    wi,
+                    f: r / wi.z().abs(),
+                    pdf: pr,
+                });
+            }
+            let wi = refract_facing(wo, Vec3::new(0.0, 0.0, 1.0), self.eta)?;
+            return Some(InterfaceSample {
+                wi,
+                f: (1.0 - r) * scale / wi.z().abs(),
+                pdf: pt,
+            });
+        }
+
+        let wm = self.distribution.sample_wm(wo, random_f64(), random_f64());
+        let r = fresnel_dielectric(dot(wo, wm), self.eta);
+        let (pr, _) = lobes.split(r, 1.0 - r)?;
+        let wi = if random_f64() < pr {
+            let wi = reflect_local(wo, wm);
+            if wi.z() * wo.z() <= 0.0 {
+                return None;
+            }
+            wi
+        } else {
+            let wi = refract_facing(wo, wm, self.eta)?;
+            if wi.z() * wo.z() >= 0.0 {
+                return None;
+            }
+            wi
+        };
+        let (f, pdf) = (self.f(wo, wi, mode), self.pdf(wo, wi, lobes));
+        if f <= 0.0 || pdf <= 0.0 {
+            return None;
+        }
+        Some(InterfaceSample { wi, f, pdf })
+    }
+
+    // Generalized half vector of the pair, facing up, and n_t / n_i for transmission.
+    fn half_vector(&self, wo: Vec3, wi: Vec3) -> Option<(Vec3, f64)> {
+        let (cos_o, cos_i) = (wo.z(), wi.z());
+        if cos_o == 0.0 || cos_i == 0.0 {
+            return None;
+        }
+        let etap = if cos_o * cos_i > 0.0 {
+            1.0
+        } else {
+            self.etap(wo)
+        };
+        let wm = etap * wi + wo;
+        if wm.length_squared() == 0.0 {
+            return None;
+        }
+        let wm = unit_vector(wm);
+        let wm = if wm.z() < 0.0 { -wm } else { wm };
+        // Microfacets seen from behind do not contribute.
+        if dot(wm, wi) * cos_i < 0.0 || dot(wm, wo) * cos_o < 0.0 {
+            return None;
+        }
+        Some((wm, etap))
+    }
+
+    fn f(&self, wo: Vec3, wi: Vec3, mode: Mode) -> f64 {
+        if self.is_smooth() {
+            return 0.0;
+        }
+        let (wm, etap) = match self.half_vector(wo, wi) {
+            Some(h) => h,
+            None => return 0.0,
+        };
+        let (cos_o, cos_i) = (wo.z(), wi.z());
+        let fresnel = fresnel_dielectric(dot(wo, wm), self.eta);
+        let dg = self.distribution.d(wm) * self.distribution.g(wo, wi);
+        if cos_o * cos_i > 0.0 {
+            return dg * fresnel / (4.0 * cos_i * cos_o).abs();
+        }
+        let denom = (dot(wi, wm) + dot(wo, wm) / etap).powi(2) * cos_i * cos_o;
+        let ft = dg * (1.0 - fresnel) * (dot(wi, wm) * dot(wo, wm) / denom).abs();
+        if mode == Mode::Radiance {
+            ft / (etap * etap)
+        } else {
+            ft
+        }
+    }
+
+    fn pdf(&self, wo: Vec3, wi: Vec3, lobes: Lobes) -> f64 {
+        if self.is_smooth() {
+            return 0.0;
+        }
+        let (wm, etap) = match self.half_vector(wo, wi) {
+            Some(h) => h,
+            None => return 0.0,
+        };
+        let r = fresnel_dielectric(dot(wo, wm), self.eta);
+        let (pr, pt) = match lobes.split(r, 1.0 - r) {
+            Some(split) => split,
+            None => return 0.0,
+        };
+        if wo.z() * wi.z() > 0.0 {
+            self.distribution.pdf(wo, wm) / (4.0 * dot(wo, wm).abs()) * pr
+        } else {
+            let denom = (dot(wi, wm) + dot(wo, wm) / etap).powi(2);
+            self.distribution.pdf(wo, wm) * dot(wi, wm).abs() / denom * pt
+        }
+    }
+}
+
+// Refracts `wo` through a surface with normal `n`, from either side. `eta` is n_t / n_i for
+// `wo` on the side `n` points to.
+fn refract_facing(wo: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    if dot(wo, n) < 0.0 {
+        refract_local(wo, -n, 1.0 / eta)
+    } else {
+        refract_local(wo, n, eta)
+    }
+}
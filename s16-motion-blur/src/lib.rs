@@ -5,4 +5,39 @@ pub mod hittable;
 pub mod sphere;
 pub mod camera;
 pub mod util;
-pub mod material;
\ No newline at end of file
+pub mod material;
+pub mod background;
+pub mod sky;
+pub mod onb;
+pub mod microfacet;
+pub mod integrator;
+pub mod spectrum;
+pub mod light;
+pub mod scene;
+pub mod bdpt;
+pub mod film;
+pub mod kdtree;
+pub mod photon;
+pub mod aabb;
+pub mod light_sampler;
+pub mod perlin;
+pub mod medium;
+pub mod medium_stack;
+pub mod texture;
+pub mod disney;
+pub mod layered;
+pub mod quad;
+pub mod triangle;
+pub mod cutout;
+pub mod bump;
+pub mod thin_film;
+pub mod sheen;
+pub mod bvh;
+pub mod curve;
+pub mod hair;
+pub mod polynomial;
+pub mod disk;
+pub mod cylinder;
+pub mod torus;
+pub mod quadric;
+pub mod implicit;
\ No newline at end of file
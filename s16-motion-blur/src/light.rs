@@ -0,0 +1,528 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    light_sampler::{DirectionCone, LightBounds},
+    onb::Onb,
+    ray::Ray,
+    util::random_f64,
+    vec3::{dot, random_in_unit_disk, unit_vector, Color, Point3, Vec3},
+};
+
+pub struct LightSample {
+    pub wi: Vec3,
+    pub distance: f64,
+    pub radiance: Color,
+    pub pdf: f64,
+}
+
+// A ray leaving the light, used to start light subpaths. `pdf_pos` is an area density
+// (or 1 for a delta position) and `pdf_dir` a solid angle density.
+pub struct EmissionSample {
+    pub ray: Ray,
+    pub normal: Vec3,
+    pub radiance: Color,
+    pub pdf_pos: f64,
+    pub pdf_dir: f64,
+}
+
+pub trait Light: Send + Sync {
+    // Samples a unit direction from `p` towards the light with its solid angle density.
+    fn sample_li(&self, p: Point3) -> Option<LightSample>;
+
+    fn pdf_li(&self, p: Point3, wi: Vec3) -> f64;
+
+    fn sample_le(&self) -> Option<EmissionSample> {
+        None
+    }
+
+    // Densities with which `sample_le` starts at `p` (normal `n`) and leaves in direction `w`.
+    fn pdf_le(&self, _p: Point3, _n: Vec3, _w: Vec3) -> (f64, f64) {
+        (0.0, 0.0)
+    }
+
+    // Radiance leaving the point `p` on the light with normal `n` in direction `w`.
+    fn l(&self, _p: Point3, _n: Vec3, _w: Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // Radiance arriving along `r` from a light at infinity, for rays that escape the scene.
+    fn le(&self, _r: &Ray) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    // Lights at infinity have no position to connect to; `sample_li` reports an infinite
+    // distance for them.
+    fn is_infinite(&self) -> bool {
+        false
+    }
+
+    // Total emitted power, averaged over the color channels.
+    fn power(&self) -> f64 {
+        0.0
+    }
+
+    // Spatial and directional extent for the light BVH. None for lights at infinity.
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+
+    // Called with the bounds of the scene geometry when the light is added to a scene.
+    fn preprocess(&mut self, _scene_bounds: &Aabb) {}
+}
+
+fn average(c: Color) -> f64 {
+    (c.x() + c.y() + c.z()) / 3.0
+}
+
+// Rays per side of the grids `emitted_power` casts.
+const POWER_GRID: usize = 8;
+
+// Power of an emissive shape, assuming uniform emission from its front face. The radiance is
+// averaged over the surface by grids of rays cast at its bounds along the six axis directions,
+// with each hit weighted by the inverse cosine to undo the projection, so the estimate is the
+// same on every build and follows textured emission.
+fn emitted_power(hittable: &Hittable) -> f64 {
+    let area = hittable.shape.area();
+    let bounds = match hittable.shape.bounding_box() {
+        Some(bounds) if area > 0.0 => bounds,
+        _ => return 0.0,
+    };
+    let extent = bounds.diagonal();
+    let mut rec = HitRecord::new(Arc::clone(&hittable.material));
+    let (mut sum, mut weight) = (0.0, 0.0);
+    for axis in 0..3 {
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+        for sign in [1.0, -1.0] {
+            let mut direction = Vec3::new(0.0, 0.0, 0.0);
+            direction[axis] = sign;
+            let mut origin = bounds.centroid() - (extent[axis] + 1.0) * direction;
+            for i in 0..POWER_GRID {
+                for j in 0..POWER_GRID {
+                    origin[a] = bounds.min[a] + (i as f64 + 0.5) / POWER_GRID as f64 * extent[a];
+                    origin[b] = bounds.min[b] + (j as f64 + 0.5) / POWER_GRID as f64 * extent[b];
+                    rec.reset();
+                    let r = Ray::new(origin, direction);
+                    if !hittable.shape.hit(r, 0.0, f64::INFINITY, &mut rec) {
+                        continue;
+                    }
+                    // Grazing hits would carry huge weights.
+                    let cosine = dot(rec.geometric_normal, direction).abs();
+                    if cosine < 0.1 {
+                        continue;
+                    }
+                    let outward = if rec.front_face {
+                        rec.geometric_normal
+                    } else {
+                        -rec.geometric_normal
+                    };
+                    (rec.normal, rec.geometric_normal) = (outward, outward);
+                    rec.front_face = true;
+                    sum += average(hittable.material.emitted(&rec)) / cosine;
+                    weight += 1.0 / cosine;
+                }
+            }
+        }
+    }
+    if weight == 0.0 {
+        return 0.0;
+    }
+    PI * area * sum / weight
+}
+
+// An emissive shape, sampled through `Shape::random` and `Shape::pdf_value`.
+pub struct AreaLight {
+    hittable: Hittable,
+    power: f64,
+}
+
+impl AreaLight {
+    pub fn new(hittable: Hittable) -> Self {
+        Self {
+            power: emitted_power(&hittable),
+            hittable,
+        }
+    }
+}
+
+impl Light for AreaLight {
+    fn sample_li(&self, p: Point3) -> Option<LightSample> {
+        let wi = unit_vector(self.hittable.shape.random(p));
+        let pdf = self.hittable.shape.pdf_value(p, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        let mut rec = HitRecord::new(Arc::clone(&self.hittable.material));
+        if !self
+            .hittable
+            .shape
+            .hit(Ray::new(p, wi), 0.001, f64::INFINITY, &mut rec)
+        {
+            return None;
+        }
+
+        Some(LightSample {
+            wi,
+            distance: rec.t,
+            radiance: rec.material.emitted(&rec),
+            pdf,
+        })
+    }
+
+    fn pdf_li(&self, p: Point3, wi: Vec3) -> f64 {
+        self.hittable.shape.pdf_value(p, wi)
+    }
+
+    fn sample_le(&self) -> Option<EmissionSample> {
+        let (p, n) = self.hittable.shape.sample_area()?;
+        let area = self.hittable.shape.area();
+
+        // Cosine-weighted emission about the outward normal.
+        let local = Vec3::random_cosine_direction();
+        let w = Onb::build_from_w(n).local(local);
+        let radiance = self.l(p, n, w);
+
+        Some(EmissionSample {
+            ray: Ray::new(p, w),
+            normal: n,
+            radiance,
+            pdf_pos: 1.0 / area,
+            pdf_dir: local.z() / PI,
+        })
+    }
+
+    fn pdf_le(&self, _p: Point3, n: Vec3, w: Vec3) -> (f64, f64) {
+        let area = self.hittable.shape.area();
+        if area <= 0.0 {
+            return (0.0, 0.0);
+        }
+        (1.0 / area, dot(n, unit_vector(w)).max(0.0) / PI)
+    }
+
+    fn l(&self, p: Point3, n: Vec3, w: Vec3) -> Color {
+        let mut rec = HitRecord::new(Arc::clone(&self.hittable.material));
+        rec.p = p;
+        rec.front_face = dot(n, w) > 0.0;
+        rec.normal = if rec.front_face { n } else { -n };
+        rec.geometric_normal = rec.normal;
+        self.hittable.material.emitted(&rec)
+    }
+
+    fn power(&self) -> f64 {
+        self.power
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let bounds = self.hittable.shape.bounding_box()?;
+        Some(LightBounds::new(
+            bounds,
+            self.power,
+            DirectionCone::entire_sphere(),
+            0.0,
+            false,
+        ))
+    }
+}
+
+// An isotropic point source. `intensity` is radiant intensity, falling off with the inverse
+// square of the distance.
+pub struct PointLight {
+    position: Point3,
+    intensity: Color,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> Self {
+        Self {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, p: Point3) -> Option<LightSample> {
+        let d = self.position - p;
+        let distance = d.length();
+        if distance == 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            wi: d / distance,
+            distance,
+            radiance: self.intensity / (distance * distance),
+            pdf: 1.0,
+        })
+    }
+
+    fn pdf_li(&self, _p: Point3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    fn sample_le(&self) -> Option<EmissionSample> {
+        Some(EmissionSample {
+            ray: Ray::new(self.position, Vec3::random_unit_vector()),
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            radiance: self.intensity,
+            pdf_pos: 1.0,
+            pdf_dir: 1.0 / (4.0 * PI),
+        })
+    }
+
+    fn pdf_le(&self, _p: Point3, _n: Vec3, _w: Vec3) -> (f64, f64) {
+        (1.0, 1.0 / (4.0 * PI))
+    }
+
+    fn l(&self, _p: Point3, _n: Vec3, _w: Vec3) -> Color {
+        self.intensity
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn power(&self) -> f64 {
+        4.0 * PI * average(self.intensity)
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds::new(
+            Aabb::point(self.position),
+            self.power(),
+            DirectionCone::entire_sphere(),
+            0.0,
+            false,
+        ))
+    }
+}
+
+// A point source restricted to a cone around `direction`. Intensity is constant up to
+// `falloff_start` degrees off axis and falls smoothly to zero at `total_width` degrees.
+pub struct SpotLight {
+    position: Point3,
+    frame: Onb,
+    intensity: Color,
+    cos_total_width: f64,
+    cos_falloff_start: f64,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: Point3,
+        direction: Vec3,
+        intensity: Color,
+        total_width: f64,
+        falloff_start: f64,
+    ) -> Self {
+        let total_width = total_width.clamp(0.0, 180.0);
+        let falloff_start = falloff_start.clamp(0.0, total_width);
+        Self {
+            position,
+            frame: Onb::build_from_w(direction),
+            intensity,
+            cos_total_width: total_width.to_radians().cos(),
+            cos_falloff_start: falloff_start.to_radians().cos(),
+        }
+    }
+
+    pub fn look_at(
+        position: Point3,
+        target: Point3,
+        intensity: Color,
+        total_width: f64,
+        falloff_start: f64,
+    ) -> Self {
+        Self::new(
+            position,
+            target - position,
+            intensity,
+            total_width,
+            falloff_start,
+        )
+    }
+
+    fn falloff(&self, w: Vec3) -> f64 {
+        let cos_theta = dot(unit_vector(w), self.frame.w);
+        if cos_theta < self.cos_total_width {
+            return 0.0;
+        }
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+        let delta =
+            (cos_theta - self.cos_total_width) / (self.cos_falloff_start - self.cos_total_width);
+        (delta * delta) * (delta * delta)
+    }
+
+    fn cone_pdf(&self) -> f64 {
+        1.0 / (2.0 * PI * (1.0 - self.cos_total_width))
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, p: Point3) -> Option<LightSample> {
+        let d = self.position - p;
+        let distance = d.length();
+        if distance == 0.0 {
+            return None;
+        }
+        let wi = d / distance;
+        let radiance = self.intensity * (self.falloff(-wi) / (distance * distance));
+        if radiance.length_squared() == 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            wi,
+            distance,
+            radiance,
+            pdf: 1.0,
+        })
+    }
+
+    fn pdf_li(&self, _p: Point3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    fn sample_le(&self) -> Option<EmissionSample> {
+        // Uniform over the cone of directions that receive any light.
+        let cos_theta = 1.0 - random_f64() * (1.0 - self.cos_total_width);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random_f64();
+        let w = self.frame.local(Vec3::new(
+            phi.cos() * sin_theta,
+            phi.sin() * sin_theta,
+            cos_theta,
+        ));
+
+        Some(EmissionSample {
+            ray: Ray::new(self.position, w),
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            radiance: self.intensity * self.falloff(w),
+            pdf_pos: 1.0,
+            pdf_dir: self.cone_pdf(),
+        })
+    }
+
+    fn pdf_le(&self, _p: Point3, _n: Vec3, w: Vec3) -> (f64, f64) {
+        if dot(unit_vector(w), self.frame.w) < self.cos_total_width {
+            return (1.0, 0.0);
+        }
+        (1.0, self.cone_pdf())
+    }
+
+    fn l(&self, _p: Point3, _n: Vec3, w: Vec3) -> Color {
+        self.intensity * self.falloff(w)
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    // Approximates the smooth falloff as linear in cos(theta).
+    fn power(&self) -> f64 {
+        2.0 * PI
+            * (1.0 - 0.5 * (self.cos_falloff_start + self.cos_total_width))
+            * average(self.intensity)
+    }
+
+    // The BVH uses the full intensity as a bound; the emission cone is the falloff region.
+    fn bounds(&self) -> Option<LightBounds> {
+        let cos_theta_e = (self.cos_total_width.acos() - self.cos_falloff_start.acos()).cos();
+        Some(LightBounds::new(
+            Aabb::point(self.position),
+            4.0 * PI * average(self.intensity),
+            DirectionCone::new(self.frame.w, self.cos_falloff_start),
+            cos_theta_e,
+            false,
+        ))
+    }
+}
+
+// A light infinitely far away, such as the sun, arriving from the single direction opposite
+// to `direction` with `irradiance` on a perpendicular surface. Emitting rays from it needs a
+// sphere bounding the scene, taken from the scene unless set with `with_scene_bounds`.
+pub struct DirectionalLight {
+    direction: Vec3,
+    irradiance: Color,
+    scene_bounds: Option<(Point3, f64)>,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Color) -> Self {
+        Self {
+            direction: unit_vector(direction),
+            irradiance,
+            scene_bounds: None,
+        }
+    }
+
+    pub fn with_scene_bounds(mut self, center: Point3, radius: f64) -> Self {
+        self.scene_bounds = Some((center, radius));
+        self
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _p: Point3) -> Option<LightSample> {
+        Some(LightSample {
+            wi: -self.direction,
+            distance: f64::INFINITY,
+            radiance: self.irradiance,
+            pdf: 1.0,
+        })
+    }
+
+    fn pdf_li(&self, _p: Point3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    // Rays start on a disk facing the light that covers the bounding sphere.
+    fn sample_le(&self) -> Option<EmissionSample> {
+        let (center, radius) = self.scene_bounds?;
+        let frame = Onb::build_from_w(self.direction);
+        let disk = random_in_unit_disk() * radius;
+        let origin = center - self.direction * radius + frame.local(disk);
+
+        Some(EmissionSample {
+            ray: Ray::new(origin, self.direction),
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            radiance: self.irradiance,
+            pdf_pos: 1.0 / (PI * radius * radius),
+            pdf_dir: 1.0,
+        })
+    }
+
+    fn pdf_le(&self, _p: Point3, _n: Vec3, _w: Vec3) -> (f64, f64) {
+        match self.scene_bounds {
+            Some((_, radius)) => (1.0 / (PI * radius * radius), 0.0),
+            None => (0.0, 0.0),
+        }
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+
+    fn power(&self) -> f64 {
+        match self.scene_bounds {
+            Some((_, radius)) => PI * radius * radius * average(self.irradiance),
+            None => 0.0,
+        }
+    }
+
+    fn preprocess(&mut self, scene_bounds: &Aabb) {
+        if self.scene_bounds.is_none() && !scene_bounds.is_empty() {
+            self.scene_bounds = Some(scene_bounds.bounding_sphere());
+        }
+    }
+}
@@ -0,0 +1,555 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    light::Light,
+    vec3::{cross, dot, unit_vector, Point3, Vec3},
+};
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON;
+
+// Chooses which light to sample at a shading point.
+pub trait LightSampler: Send + Sync {
+    // Picks a light for the point `p` with surface normal `n` (zero if there is no surface),
+    // returning its index into the scene lights and the probability of choosing it.
+    fn sample(&self, p: Point3, n: Vec3, u: f64) -> Option<(usize, f64)>;
+
+    fn pmf(&self, p: Point3, n: Vec3, light: usize) -> f64;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightSampling {
+    Uniform,
+    // Proportional to emitted power, ignoring the shading point.
+    Power,
+    // Proportional to an estimate of each light's contribution at the shading point.
+    Bvh,
+}
+
+impl LightSampling {
+    pub fn build(&self, lights: &[Arc<dyn Light>]) -> Box<dyn LightSampler> {
+        match self {
+            LightSampling::Uniform => Box::new(UniformLightSampler::new(lights)),
+            LightSampling::Power => Box::new(PowerLightSampler::new(lights)),
+            LightSampling::Bvh => Box::new(BvhLightSampler::new(lights)),
+        }
+    }
+}
+
+pub struct UniformLightSampler {
+    n_lights: usize,
+}
+
+impl UniformLightSampler {
+    pub fn new(lights: &[Arc<dyn Light>]) -> Self {
+        Self {
+            n_lights: lights.len(),
+        }
+    }
+}
+
+impl LightSampler for UniformLightSampler {
+    fn sample(&self, _p: Point3, _n: Vec3, u: f64) -> Option<(usize, f64)> {
+        if self.n_lights == 0 {
+            return None;
+        }
+        let light = ((u * self.n_lights as f64) as usize).min(self.n_lights - 1);
+        Some((light, 1.0 / self.n_lights as f64))
+    }
+
+    fn pmf(&self, _p: Point3, _n: Vec3, light: usize) -> f64 {
+        if light >= self.n_lights {
+            return 0.0;
+        }
+        1.0 / self.n_lights as f64
+    }
+}
+
+// Falls back to uniform selection when no light reports any power.
+pub struct PowerLightSampler {
+    cdf: Vec<f64>,
+    pmf: Vec<f64>,
+}
+
+impl PowerLightSampler {
+    pub fn new(lights: &[Arc<dyn Light>]) -> Self {
+        let mut weights: Vec<f64> = lights.iter().map(|l| l.power().max(0.0)).collect();
+        let mut total: f64 = weights.iter().sum();
+        if total <= 0.0 || !total.is_finite() {
+            weights = vec![1.0; lights.len()];
+            total = lights.len() as f64;
+        }
+
+        let pmf: Vec<f64> = weights.iter().map(|w| w / total).collect();
+        let mut cdf = Vec::with_capacity(pmf.len());
+        let mut sum = 0.0;
+        for p in pmf.iter() {
+            sum += p;
+            cdf.push(sum);
+        }
+        Self { cdf, pmf }
+    }
+}
+
+impl LightSampler for PowerLightSampler {
+    fn sample(&self, _p: Point3, _n: Vec3, u: f64) -> Option<(usize, f64)> {
+        if self.pmf.is_empty() {
+            return None;
+        }
+        let light = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .min(self.pmf.len() - 1);
+        if self.pmf[light] == 0.0 {
+            return None;
+        }
+        Some((light, self.pmf[light]))
+    }
+
+    fn pmf(&self, _p: Point3, _n: Vec3, light: usize) -> f64 {
+        self.pmf.get(light).copied().unwrap_or(0.0)
+    }
+}
+
+// A cone of directions about the unit axis `w`.
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionCone {
+    pub w: Vec3,
+    pub cos_theta: f64,
+}
+
+impl DirectionCone {
+    pub fn new(w: Vec3, cos_theta: f64) -> Self {
+        Self {
+            w: unit_vector(w),
+            cos_theta,
+        }
+    }
+
+    pub fn entire_sphere() -> Self {
+        Self::new(Vec3::new(0.0, 0.0, 1.0), -1.0)
+    }
+
+    pub fn union(&self, other: &DirectionCone) -> Self {
+        let theta_a = safe_acos(self.cos_theta);
+        let theta_b = safe_acos(other.cos_theta);
+        let theta_d = safe_acos(dot(self.w, other.w));
+        if (theta_d + theta_b).min(PI) <= theta_a {
+            return *self;
+        }
+        if (theta_d + theta_a).min(PI) <= theta_b {
+            return *other;
+        }
+
+        // Spread the merged cone from the far edge of one cone to the far edge of the other.
+        let theta_o = 0.5 * (theta_a + theta_d + theta_b);
+        if theta_o >= PI {
+            return Self::entire_sphere();
+        }
+        let axis = cross(self.w, other.w);
+        if axis.length_squared() == 0.0 {
+            return Self::entire_sphere();
+        }
+        let w = rotate(self.w, unit_vector(axis), theta_o - theta_a);
+        Self::new(w, theta_o.cos())
+    }
+}
+
+// What the light BVH knows about a light or a cluster of lights: where it is, how much it
+// emits, and the directions it emits into. Normals lie within `theta_o` of `w` and light leaves
+// at most `theta_e` away from a normal.
+#[derive(Debug, Clone, Copy)]
+pub struct LightBounds {
+    pub bounds: Aabb,
+    pub phi: f64,
+    pub normals: DirectionCone,
+    pub cos_theta_e: f64,
+    pub two_sided: bool,
+}
+
+impl LightBounds {
+    pub fn new(
+        bounds: Aabb,
+        phi: f64,
+        normals: DirectionCone,
+        cos_theta_e: f64,
+        two_sided: bool,
+    ) -> Self {
+        Self {
+            bounds,
+            phi,
+            normals,
+            cos_theta_e,
+            two_sided,
+        }
+    }
+
+    pub fn union(&self, other: &LightBounds) -> Self {
+        if self.phi == 0.0 {
+            return *other;
+        }
+        if other.phi == 0.0 {
+            return *self;
+        }
+        Self {
+            bounds: self.bounds.union(&other.bounds),
+            phi: self.phi + other.phi,
+            normals: self.normals.union(&other.normals),
+            cos_theta_e: self.cos_theta_e.min(other.cos_theta_e),
+            two_sided: self.two_sided || other.two_sided,
+        }
+    }
+
+    // Conservative estimate of the light arriving at `p` from the bounded lights, after Conty
+    // Estevez and Kulla, "Importance Sampling of Many Lights with Adaptive Tree Splitting".
+    pub fn importance(&self, p: Point3, n: Vec3) -> f64 {
+        let pc = self.bounds.centroid();
+        let d2 = (p - pc)
+            .length_squared()
+            .max(0.5 * self.bounds.diagonal().length());
+
+        let to_p = p - pc;
+        let wi = if to_p.length_squared() > 0.0 {
+            unit_vector(to_p)
+        } else {
+            self.normals.w
+        };
+        let mut cos_theta_w = dot(self.normals.w, wi);
+        if self.two_sided {
+            cos_theta_w = cos_theta_w.abs();
+        }
+        let sin_theta_w = safe_sqrt(1.0 - cos_theta_w * cos_theta_w);
+
+        // Angle subtended by the bounds as seen from `p`.
+        let (center, radius) = self.bounds.bounding_sphere();
+        let distance_squared = (p - center).length_squared();
+        let cos_theta_b = if distance_squared < radius * radius {
+            -1.0
+        } else {
+            safe_sqrt(1.0 - radius * radius / distance_squared)
+        };
+        let sin_theta_b = safe_sqrt(1.0 - cos_theta_b * cos_theta_b);
+
+        let cos_theta_o = self.normals.cos_theta;
+        let sin_theta_o = safe_sqrt(1.0 - cos_theta_o * cos_theta_o);
+        let cos_theta_x = cos_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, cos_theta_o);
+        let sin_theta_x = sin_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, cos_theta_o);
+        let cos_theta_p = cos_sub_clamped(sin_theta_x, cos_theta_x, sin_theta_b, cos_theta_b);
+        if cos_theta_p <= self.cos_theta_e {
+            return 0.0;
+        }
+
+        let mut importance = self.phi * cos_theta_p / d2;
+        if n.length_squared() > 0.0 {
+            let cos_theta_i = dot(wi, n).abs();
+            let sin_theta_i = safe_sqrt(1.0 - cos_theta_i * cos_theta_i);
+            importance *= cos_sub_clamped(sin_theta_i, cos_theta_i, sin_theta_b, cos_theta_b);
+        }
+        importance.max(0.0)
+    }
+
+    // Surface area orientation heuristic used to choose BVH splits, penalizing thin slabs of
+    // the node `node_bounds` split along `axis`.
+    fn cost(&self, node_bounds: &Aabb, axis: usize) -> f64 {
+        let cos_theta_o = self.normals.cos_theta;
+        let theta_o = safe_acos(cos_theta_o);
+        let theta_e = safe_acos(self.cos_theta_e);
+        let theta_w = (theta_o + theta_e).min(PI);
+        let sin_theta_o = safe_sqrt(1.0 - cos_theta_o * cos_theta_o);
+        let m_omega = 2.0 * PI * (1.0 - cos_theta_o)
+            + PI / 2.0
+                * (2.0 * theta_w * sin_theta_o
+                    - (theta_o - 2.0 * theta_w).cos()
+                    - 2.0 * theta_o * sin_theta_o
+                    + cos_theta_o);
+
+        let d = node_bounds.diagonal();
+        let kr = if d[axis] > 0.0 {
+            d.x().max(d.y()).max(d.z()) / d[axis]
+        } else {
+            1.0
+        };
+        self.phi * m_omega * kr * self.bounds.surface_area()
+    }
+}
+
+struct LightBvhNode {
+    bounds: LightBounds,
+    // The light of a leaf, or the second child of an interior node. The first child of an
+    // interior node directly follows it.
+    index: usize,
+    is_leaf: bool,
+}
+
+#[derive(Clone, Copy)]
+enum LightLocation {
+    Unsampled,
+    Infinite,
+    // Root-to-leaf path, one bit per level with 1 for the second child.
+    Bvh(u64),
+}
+
+// Lights without bounds, such as directional lights, are chosen uniformly with the same total
+// probability as the whole tree.
+pub struct BvhLightSampler {
+    nodes: Vec<LightBvhNode>,
+    infinite_lights: Vec<usize>,
+    locations: Vec<LightLocation>,
+}
+
+const N_BUCKETS: usize = 12;
+const MAX_SAH_DEPTH: u32 = 40;
+
+impl BvhLightSampler {
+    pub fn new(lights: &[Arc<dyn Light>]) -> Self {
+        let mut sampler = Self {
+            nodes: Vec::new(),
+            infinite_lights: Vec::new(),
+            locations: vec![LightLocation::Unsampled; lights.len()],
+        };
+
+        let mut bounded = Vec::new();
+        for (i, light) in lights.iter().enumerate() {
+            match light.bounds() {
+                Some(lb) if lb.phi > 0.0 => bounded.push((i, lb)),
+                Some(_) => {}
+                None => {
+                    sampler.locations[i] = LightLocation::Infinite;
+                    sampler.infinite_lights.push(i);
+                }
+            }
+        }
+        if !bounded.is_empty() {
+            sampler.build(&mut bounded, 0, 0);
+        }
+        sampler
+    }
+
+    fn build(&mut self, lights: &mut [(usize, LightBounds)], trail: u64, depth: u32) -> usize {
+        let node_index = self.nodes.len();
+        if lights.len() == 1 {
+            let (light, bounds) = lights[0];
+            self.nodes.push(LightBvhNode {
+                bounds,
+                index: light,
+                is_leaf: true,
+            });
+            self.locations[light] = LightLocation::Bvh(trail);
+            return node_index;
+        }
+
+        let mut bounds = Aabb::empty();
+        let mut centroid_bounds = Aabb::empty();
+        for (_, lb) in lights.iter() {
+            bounds = bounds.union(&lb.bounds);
+            centroid_bounds = centroid_bounds.union_point(lb.bounds.centroid());
+        }
+
+        let mid = if depth < MAX_SAH_DEPTH {
+            split_by_cost(lights, &bounds, &centroid_bounds)
+        } else {
+            None
+        };
+        let mid = mid.unwrap_or_else(|| {
+            let axis = centroid_bounds.longest_axis();
+            let mid = lights.len() / 2;
+            lights.select_nth_unstable_by(mid, |a, b| {
+                a.1.bounds.centroid()[axis].total_cmp(&b.1.bounds.centroid()[axis])
+            });
+            mid
+        });
+
+        self.nodes.push(LightBvhNode {
+            bounds: lights[0].1,
+            index: 0,
+            is_leaf: false,
+        });
+        let (first, second) = lights.split_at_mut(mid);
+        let c0 = self.build(first, trail, depth + 1);
+        let c1 = self.build(second, trail | (1 << depth), depth + 1);
+
+        let node_bounds = self.nodes[c0].bounds.union(&self.nodes[c1].bounds);
+        self.nodes[node_index].bounds = node_bounds;
+        self.nodes[node_index].index = c1;
+        node_index
+    }
+
+    fn infinite_probability(&self) -> f64 {
+        let n_infinite = self.infinite_lights.len() as f64;
+        let n_tree = if self.nodes.is_empty() { 0.0 } else { 1.0 };
+        if n_infinite + n_tree == 0.0 {
+            return 0.0;
+        }
+        n_infinite / (n_infinite + n_tree)
+    }
+}
+
+// Partitions `lights` at the cheapest of the bucket boundaries along any axis. Returns None if
+// no split leaves lights on both sides.
+fn split_by_cost(
+    lights: &mut [(usize, LightBounds)],
+    bounds: &Aabb,
+    centroid_bounds: &Aabb,
+) -> Option<usize> {
+    let bucket_of = |lb: &LightBounds, axis: usize| {
+        let b = (N_BUCKETS as f64 * centroid_bounds.offset(lb.bounds.centroid())[axis]) as usize;
+        b.min(N_BUCKETS - 1)
+    };
+
+    let mut best: Option<(f64, usize, usize)> = None;
+    for axis in 0..3 {
+        if centroid_bounds.max[axis] == centroid_bounds.min[axis] {
+            continue;
+        }
+
+        let mut buckets: [Option<LightBounds>; N_BUCKETS] = [None; N_BUCKETS];
+        for (_, lb) in lights.iter() {
+            let b = bucket_of(lb, axis);
+            buckets[b] = Some(match buckets[b] {
+                Some(existing) => existing.union(lb),
+                None => *lb,
+            });
+        }
+
+        for split in 0..N_BUCKETS - 1 {
+            let below = buckets[..=split]
+                .iter()
+                .flatten()
+                .copied()
+                .reduce(|a, b| a.union(&b));
+            let above = buckets[split + 1..]
+                .iter()
+                .flatten()
+                .copied()
+                .reduce(|a, b| a.union(&b));
+            let (below, above) = match (below, above) {
+                (Some(below), Some(above)) => (below, above),
+                _ => continue,
+            };
+            let cost = below.cost(bounds, axis) + above.cost(bounds, axis);
+            if best.is_none_or(|(c, _, _)| cost < c) {
+                best = Some((cost, axis, split));
+            }
+        }
+    }
+
+    let (_, axis, split) = best?;
+    let mut mid = 0;
+    for i in 0..lights.len() {
+        if bucket_of(&lights[i].1, axis) <= split {
+            lights.swap(i, mid);
+            mid += 1;
+        }
+    }
+    if mid == 0 || mid == lights.len() {
+        return None;
+    }
+    Some(mid)
+}
+
+impl LightSampler for BvhLightSampler {
+    fn sample(&self, p: Point3, n: Vec3, u: f64) -> Option<(usize, f64)> {
+        let p_infinite = self.infinite_probability();
+        if u < p_infinite {
+            let n_infinite = self.infinite_lights.len();
+            let i = ((u / p_infinite * n_infinite as f64) as usize).min(n_infinite - 1);
+            return Some((self.infinite_lights[i], p_infinite / n_infinite as f64));
+        }
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut u = ((u - p_infinite) / (1.0 - p_infinite)).min(ONE_MINUS_EPSILON);
+        let mut pmf = 1.0 - p_infinite;
+        let mut node_index = 0;
+        loop {
+            let node = &self.nodes[node_index];
+            if node.is_leaf {
+                if node_index > 0 || node.bounds.importance(p, n) > 0.0 {
+                    return Some((node.index, pmf));
+                }
+                return None;
+            }
+
+            let c0 = self.nodes[node_index + 1].bounds.importance(p, n);
+            let c1 = self.nodes[node.index].bounds.importance(p, n);
+            if c0 == 0.0 && c1 == 0.0 {
+                return None;
+            }
+            let p0 = c0 / (c0 + c1);
+            if u < p0 {
+                node_index += 1;
+                u = (u / p0).min(ONE_MINUS_EPSILON);
+                pmf *= p0;
+            } else {
+                node_index = node.index;
+                u = ((u - p0) / (1.0 - p0)).min(ONE_MINUS_EPSILON);
+                pmf *= 1.0 - p0;
+            }
+        }
+    }
+
+    fn pmf(&self, p: Point3, n: Vec3, light: usize) -> f64 {
+        let p_infinite = self.infinite_probability();
+        let mut trail = match self.locations.get(light) {
+            Some(LightLocation::Bvh(trail)) => *trail,
+            Some(LightLocation::Infinite) => {
+                return p_infinite / self.infinite_lights.len() as f64;
+            }
+            _ => return 0.0,
+        };
+
+        let mut pmf = 1.0 - p_infinite;
+        let mut node_index = 0;
+        loop {
+            let node = &self.nodes[node_index];
+            if node.is_leaf {
+                return pmf;
+            }
+
+            let c0 = self.nodes[node_index + 1].bounds.importance(p, n);
+            let c1 = self.nodes[node.index].bounds.importance(p, n);
+            if c0 == 0.0 && c1 == 0.0 {
+                return 0.0;
+            }
+            if trail & 1 == 0 {
+                pmf *= c0 / (c0 + c1);
+                node_index += 1;
+            } else {
+                pmf *= c1 / (c0 + c1);
+                node_index = node.index;
+            }
+            trail >>= 1;
+        }
+    }
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
+
+fn safe_acos(x: f64) -> f64 {
+    x.clamp(-1.0, 1.0).acos()
+}
+
+// cos(max(0, a - b)) from the sines and cosines of a and b.
+fn cos_sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b {
+        return 1.0;
+    }
+    cos_a * cos_b + sin_a * sin_b
+}
+
+// sin(max(0, a - b)) from the sines and cosines of a and b.
+fn sin_sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b {
+        return 0.0;
+    }
+    sin_a * cos_b - cos_a * sin_b
+}
+
+// Rotates `v` by `theta` about the unit `axis`.
+fn rotate(v: Vec3, axis: Vec3, theta: f64) -> Vec3 {
+    let (sin_theta, cos_theta) = theta.sin_cos();
+    v * cos_theta + cross(axis, v) * sin_theta + axis * (dot(axis, v) * (1.0 - cos_theta))
+}
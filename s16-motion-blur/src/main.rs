@@ -1,17 +1,19 @@
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
 use s16_motion_blur::{
+    background::Gradient,
     camera::Camera,
     color::write_color,
-    hittable::{HitRecord, Hittable},
+    film::Film,
+    hittable::Hittable,
+    integrator::Integrator,
     material::{Dielectric, Lambertian, Metal},
-    ray::Ray,
+    scene::Scene,
     sphere::Sphere,
     util::{random_f64, random_f64_range},
-    vec3::{unit_vector, Color, Point3, Vec3},
+    vec3::{Color, Point3, Vec3},
 };
 use std::fs::File;
 use std::io::{self, Write};
-use std::sync::Arc;
 
 // Image Settings
 const ASPECT_RATIO: f64 = 16.0 / 9.0;
@@ -19,60 +21,10 @@ const IMAGE_WIDTH: i32 = 160;
 const IMAGE_HEIGHT: i32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as i32;
 const SAMPLES_PER_PIXEL: i32 = 100;
 const MAX_DEPTH: i32 = 50;
+const INTEGRATOR: Integrator = Integrator::PathTracer;
 
 const COUNT_MAX: usize = IMAGE_HEIGHT as usize * IMAGE_WIDTH as usize;
 
-fn ray_color(r: Ray, world: &Vec<Hittable>, depth: i32) -> Color {
-    let mut rec = HitRecord {
-        p: Point3::new(0.0, 0.0, 0.0),
-        normal: Vec3::new(0.0, 0.0, 0.0),
-        material: Arc::new(Lambertian::new(Vec3::new(0.0, 0.0, 0.0))),
-        t: 0.0,
-        front_face: false,
-    };
-
-    if depth <= 0 {
-        return Color::new(0.0, 0.0, 0.0);
-    }
-
-    let mut closest_so_far = f64::INFINITY;
-    let mut hit_anything = false;
-    let mut temp_rec = HitRecord {
-        p: Point3::new(0.0, 0.0, 0.0),
-        normal: Vec3::new(0.0, 0.0, 0.0),
-        material: Arc::new(Lambertian::new(Vec3::new(0.0, 0.0, 0.0))),
-        t: 0.0,
-        front_face: false,
-    };
-
-    for hittable in world.iter() {
-        temp_rec.material = Arc::clone(&hittable.material);
-        if hittable.shape.hit(r, 0.001, closest_so_far, &mut temp_rec) {
-            hit_anything = true;
-            closest_so_far = temp_rec.t.clone();
-            rec.p = temp_rec.p.clone();
-            rec.normal = temp_rec.normal.clone();
-            rec.material = Arc::clone(&temp_rec.material);
-            rec.t = temp_rec.t.clone();
-            rec.front_face = temp_rec.front_face.clone();
-        }
-    }
-
-    if hit_anything {
-        let mut scattered = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
-        let mut attenuation = Color::new(0.0, 0.0, 0.0);
-
-        if Arc::clone(&rec.material).scatter(r, &rec, &mut attenuation, &mut scattered) {
-            return attenuation * ray_color(scattered, world, depth - 1);
-        }
-        return Color::new(0.0, 0.0, 0.0);
-    }
-
-    let unit_direction: Vec3 = unit_vector(r.direction());
-    let t = 0.5 * (unit_direction.y() + 1.0);
-    (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
-}
-
 fn random_scene() -> Vec<Hittable> {
     let mut world: Vec<Hittable> = Vec::new();
 
@@ -131,7 +83,7 @@ fn random_scene() -> Vec<Hittable> {
 fn main() -> io::Result<()> {
     let mut out_str = format!("P3\n{} {}\n255\n", IMAGE_WIDTH, IMAGE_HEIGHT);
 
-    let world = random_scene();
+    let scene = Scene::new(random_scene(), Gradient::default());
 
     // Camera
     let lookfrom = Point3::new(13.0, 2.0, 3.0);
@@ -150,23 +102,38 @@ fn main() -> io::Result<()> {
         dist_to_focus,
     );
 
-    let mut data_array_raw: Vec<[u8; 3]> = vec![[0; 3]; COUNT_MAX];
+    let film = Film::new(IMAGE_WIDTH as usize, IMAGE_HEIGHT as usize);
+    let mut pixel_colors: Vec<Color> = vec![Color::new(0.0, 0.0, 0.0); COUNT_MAX];
+
+    // One sample per pixel per pass, so integrators can rebuild shared data between passes.
+    for pass in 0..SAMPLES_PER_PIXEL as usize {
+        let pass_state = INTEGRATOR.begin_pass(&scene, pass, MAX_DEPTH);
+
+        pixel_colors
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(idx, pixel_color)| {
+                let x = idx % IMAGE_WIDTH as usize;
+                let y = IMAGE_HEIGHT - 1 - (idx / IMAGE_WIDTH as usize) as i32;
+
+                let u = (x as f64 + random_f64()) / IMAGE_WIDTH as f64;
+                let v = (y as f64 + random_f64()) / IMAGE_HEIGHT as f64;
+                let r = cam.get_ray(u, v);
+                *pixel_color += INTEGRATOR.li(r, &scene, &cam, &film, &pass_state, MAX_DEPTH);
+            });
+    }
 
+    // Splats are accumulated per light path, and there is one light path per camera sample.
+    let mut data_array_raw: Vec<[u8; 3]> = vec![[0; 3]; COUNT_MAX];
     data_array_raw
         .par_iter_mut()
         .enumerate()
         .for_each(|(idx, pixel)| {
-            let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-            let x = idx % IMAGE_WIDTH as usize;
-            let y = IMAGE_HEIGHT - (idx / IMAGE_WIDTH as usize) as i32;
-
-            for _ in 0..SAMPLES_PER_PIXEL {
-                let u = (x as f64 + random_f64()) / (IMAGE_WIDTH - 1) as f64;
-                let v = (y as f64 + random_f64()) / (IMAGE_HEIGHT - 1) as f64;
-                let r = cam.get_ray(u, v);
-                pixel_color += ray_color(r, &world, MAX_DEPTH);
-            }
-            write_color(pixel_color, SAMPLES_PER_PIXEL, pixel);
+            write_color(
+                pixel_colors[idx] + film.splat(idx),
+                SAMPLES_PER_PIXEL,
+                pixel,
+            );
         });
 
     print!("\nWriting to file...");
@@ -1,6 +1,16 @@
+use std::{f64::consts::PI, sync::Arc};
+
 use crate::{
     hittable::{HitRecord, Material},
+    medium_stack::Interior,
+    microfacet::{
+        fresnel_conductor, fresnel_dielectric, reflect_local, refract_local, TrowbridgeReitz,
+    },
+    onb::Onb,
     ray::Ray,
+    spectrum::rgb_to_spectrum,
+    texture::Texture,
+    thin_film::ThinFilm,
     util::random_f64,
     vec3::{dot, reflect, refract, unit_vector, Color, Vec3},
 };
@@ -29,16 +39,55 @@ impl Material for Lambertian {
         *attenuation = self.albedo;
         true
     }
+
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        self.pdf(rec, wo, wi) * self.albedo
+    }
+
+    fn pdf(&self, rec: &HitRecord, _wo: Vec3, wi: Vec3) -> f64 {
+        dot(wi, rec.normal).max(0.0) / PI
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
 }
 
 pub struct Metal {
     pub albedo: Color,
     pub fuzz: f64,
+    pub thin_film: Option<ThinFilm>,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Self {
-        Self { albedo, fuzz }
+        Self {
+            albedo,
+            fuzz,
+            thin_film: None,
+        }
+    }
+
+    // Coats the metal with an iridescent film. Its reflectance then follows from the film on
+    // a conductor whose normal incidence reflectance is `albedo`.
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    // Reflectance from `wo` into `wi`, at the hit's wavelength in spectral mode.
+    fn reflectance(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let film = match &self.thin_film {
+            Some(film) => film,
+            None => return self.albedo,
+        };
+        let cos_theta = dot(wo, unit_vector(wo + wi));
+        film.reflectance(rec, rec.wavelength, cos_theta, rec.ior, |lambda| {
+            // The complex index with normal incidence reflectance r and no edge tint
+            // (Gulbrandsen, "Artist Friendly Metallic Fresnel").
+            let r = rgb_to_spectrum(self.albedo, lambda).clamp(0.0, 0.999);
+            ((1.0 - r) / (1.0 + r), 2.0 * r.sqrt() / (1.0 + r))
+        })
     }
 }
 
@@ -52,18 +101,289 @@ impl Material for Metal {
     ) -> bool {
         let reflected = reflect(unit_vector(r_in.direction()), rec.normal);
         *scattered = Ray::new(rec.p, reflected + self.fuzz * Vec3::random_in_unit_sphere());
-        *attenuation = self.albedo;
-        dot(scattered.direction(), rec.normal) > 0.0
+        if dot(scattered.direction(), rec.normal) <= 0.0 {
+            return false;
+        }
+        let wo = -unit_vector(r_in.direction());
+        let wi = unit_vector(scattered.direction());
+        *attenuation = self.reflectance(rec, wo, wi);
+        true
+    }
+
+    // Directions below the surface are absorbed by `scatter`, so f * cos / pdf is the albedo.
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        if dot(wi, rec.normal) <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.pdf(rec, wo, wi) * self.reflectance(rec, wo, wi)
+    }
+
+    // `scatter` picks a point uniformly in the ball of radius `fuzz` around the mirror
+    // direction, so the density of `wi` is the ball volume swept by that ray, t^2 dt.
+    fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        if self.fuzz <= 0.0 {
+            return 0.0;
+        }
+        let reflected = reflect(-wo, rec.normal);
+        let b = dot(wi, reflected);
+        let discriminant = b * b - (reflected.length_squared() - self.fuzz * self.fuzz);
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+        let t_far = b + discriminant.sqrt();
+        if t_far <= 0.0 {
+            return 0.0;
+        }
+        let t_near = (b - discriminant.sqrt()).max(0.0);
+        (t_far.powi(3) - t_near.powi(3)) / (4.0 * PI * self.fuzz.powi(3))
+    }
+
+    fn is_specular(&self) -> bool {
+        self.fuzz <= 0.0
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.thin_film.is_some()
+    }
+}
+
+pub struct Conductor {
+    pub eta: Color,
+    pub k: Color,
+    // Roughness along x follows the tangent, by default the surface's u direction (dpdu).
+    pub distribution: TrowbridgeReitz,
+    // Tangent directions in the (dpdu, dpdv) plane, encoded as 0.5 * t + 0.5 in the first two
+    // channels, such as circles for turned parts.
+    pub tangent_map: Option<Arc<dyn Texture>>,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Self {
+        Self {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::from_roughness(roughness),
+            tangent_map: None,
+        }
+    }
+
+    // Brushed metal: separate roughness along and across the tangent.
+    pub fn with_anisotropic_roughness(mut self, roughness_x: f64, roughness_y: f64) -> Self {
+        self.distribution =
+            TrowbridgeReitz::anisotropic(roughness_x * roughness_x, roughness_y * roughness_y);
+        self
+    }
+
+    pub fn with_tangent_map<T: 'static + Texture>(mut self, tangent_map: T) -> Self {
+        self.tangent_map = Some(Arc::new(tangent_map));
+        self
+    }
+
+    fn frame(&self, rec: &HitRecord) -> Onb {
+        let tangent = match &self.tangent_map {
+            Some(map) => {
+                let t = 2.0 * map.value(rec.u, rec.v, rec.p) - Color::new(1.0, 1.0, 1.0);
+                let dpdu = rec.dpdu;
+                let dpdv = rec.dpdv;
+                let (du, dv) = (dpdu.length(), dpdv.length());
+                if du == 0.0 || dv == 0.0 {
+                    dpdu
+                } else {
+                    t.x() / du * dpdu + t.y() / dv * dpdv
+                }
+            }
+            None => rec.dpdu,
+        };
+        Onb::build_from_w_and_tangent(rec.normal, tangent)
+    }
+
+    pub fn gold(roughness: f64) -> Self {
+        Self::new(
+            Color::new(0.143, 0.374, 1.442),
+            Color::new(3.983, 2.385, 1.603),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f64) -> Self {
+        Self::new(
+            Color::new(0.200, 0.924, 1.102),
+            Color::new(3.912, 2.452, 2.142),
+            roughness,
+        )
+    }
+
+    pub fn aluminium(roughness: f64) -> Self {
+        Self::new(
+            Color::new(1.657, 0.880, 0.521),
+            Color::new(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+
+    pub fn silver(roughness: f64) -> Self {
+        Self::new(
+            Color::new(0.155, 0.117, 0.138),
+            Color::new(4.828, 3.122, 2.147),
+            roughness,
+        )
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        let uvw = self.frame(rec);
+        let wo = uvw.to_local(-unit_vector(r_in.direction()));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let wm = self.distribution.sample_wm(wo, random_f64(), random_f64());
+        let wi = reflect_local(wo, wm);
+        if wi.z() <= 0.0 {
+            return false;
+        }
+
+        // With visible-normal sampling, f * cos / pdf reduces to F * G2 / G1.
+        let fresnel = fresnel_conductor(dot(wo, wm), self.eta, self.k);
+        *attenuation = fresnel * (self.distribution.g(wo, wi) / self.distribution.g1(wo));
+        *scattered = Ray::new(rec.p, uvw.local(wi));
+        true
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let uvw = self.frame(rec);
+        let (wo, wi) = (uvw.to_local(wo), uvw.to_local(wi));
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let wm = unit_vector(wo + wi);
+        let fresnel = fresnel_conductor(dot(wo, wm), self.eta, self.k);
+        fresnel * (self.distribution.d(wm) * self.distribution.g(wo, wi) / (4.0 * wo.z()))
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let uvw = self.frame(rec);
+        let (wo, wi) = (uvw.to_local(wo), uvw.to_local(wi));
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let wm = unit_vector(wo + wi);
+        self.distribution.pdf(wo, wm) / (4.0 * dot(wo, wm))
+    }
+
+    fn is_specular(&self) -> bool {
+        self.distribution.effectively_smooth()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Dispersion {
+    None,
+    // n = a + b / lambda^2, lambda in micrometres.
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum(B_i lambda^2 / (lambda^2 - C_i)), lambda in micrometres.
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    pub fn ior(&self, wavelength_nm: f64) -> Option<f64> {
+        let l = wavelength_nm * 1e-3;
+        let l2 = l * l;
+        match *self {
+            Dispersion::None => None,
+            Dispersion::Cauchy { a, b } => Some(a + b / l2),
+            Dispersion::Sellmeier { b, c } => {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>();
+                Some(n2.sqrt())
+            }
+        }
     }
 }
 
 pub struct Dielectric {
     pub ir: f64,
+    pub absorption: Color,
+    pub dispersion: Dispersion,
+    // Where dielectrics overlap, the one with the highest priority fills the overlap.
+    pub priority: i32,
+    pub thin_film: Option<ThinFilm>,
 }
 
 impl Dielectric {
     pub fn new(ir: f64) -> Self {
-        Self { ir }
+        Self::with_absorption(ir, Color::new(0.0, 0.0, 0.0))
+    }
+
+    pub fn with_absorption(ir: f64, absorption: Color) -> Self {
+        Self {
+            ir,
+            absorption,
+            dispersion: Dispersion::None,
+            priority: 0,
+            thin_film: None,
+        }
+    }
+
+    // `ir` stays the index at the sodium D line for rendering in RGB.
+    pub fn with_dispersion(dispersion: Dispersion) -> Self {
+        Self {
+            ir: dispersion.ior(589.3).unwrap_or(1.5),
+            absorption: Color::new(0.0, 0.0, 0.0),
+            dispersion,
+            priority: 0,
+            thin_film: None,
+        }
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    // Coats the surface with an iridescent film, as on soap bubbles (a film on a dielectric of
+    // index 1) or oil on water.
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    pub fn cauchy(a: f64, b: f64) -> Self {
+        Self::with_dispersion(Dispersion::Cauchy { a, b })
+    }
+
+    pub fn sellmeier(b: [f64; 3], c: [f64; 3]) -> Self {
+        Self::with_dispersion(Dispersion::Sellmeier { b, c })
+    }
+
+    pub fn bk7() -> Self {
+        Self::sellmeier(
+            [1.03961212, 0.231792344, 1.01046945],
+            [0.00600069867, 0.0200179144, 103.560653],
+        )
+    }
+
+    pub fn dense_flint() -> Self {
+        Self::sellmeier(
+            [1.73759695, 0.313747346, 1.89878101],
+            [0.013188707, 0.0623068142, 155.23629],
+        )
+    }
+
+    pub fn ior(&self, r: Ray) -> f64 {
+        self.ior_at(r.wavelength())
+    }
+
+    fn ior_at(&self, wavelength: Option<f64>) -> f64 {
+        wavelength
+            .and_then(|l| self.dispersion.ior(l))
+            .unwrap_or(self.ir)
     }
 }
 
@@ -75,30 +395,377 @@ impl Material for Dielectric {
         attenuation: &mut Vec3,
         scattered: &mut Ray,
     ) -> bool {
-        *attenuation = Vec3::new(1.0, 1.0, 1.0);
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
+        let film = match &self.thin_film {
+            Some(film) => film,
+            None => {
+                *attenuation = Color::new(1.0, 1.0, 1.0);
+                *scattered = scatter_smooth_interface(r_in, rec);
+                return true;
+            }
         };
 
+        let (n_i, n_t) = (rec.ior, rec.transmitted_ior);
         let unit_direction = unit_vector(r_in.direction());
         let cos_theta = dot(-unit_direction, rec.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let reflected = Ray::new(rec.p, reflect(unit_direction, rec.normal));
+        if n_i / n_t * sin_theta > 1.0 {
+            *attenuation = Color::new(1.0, 1.0, 1.0);
+            *scattered = reflected;
+            return true;
+        }
+
+        // Reflect or refract in proportion to the average reflectance, weighting the colours.
+        let r = film.reflectance(rec, r_in.wavelength(), cos_theta, n_i, |_| (n_t, 0.0));
+        let p = (r.x() + r.y() + r.z()) / 3.0;
+        if random_f64() < p {
+            *attenuation = r / p;
+            *scattered = reflected;
+        } else {
+            *attenuation = (Color::new(1.0, 1.0, 1.0) - r) / (1.0 - p);
+            *scattered = Ray::new(rec.p, refract(unit_direction, rec.normal, n_i / n_t));
+        }
+        true
+    }
+
+    fn is_dispersive(&self) -> bool {
+        !matches!(self.dispersion, Dispersion::None) || self.thin_film.is_some()
+    }
+
+    fn interior(&self, wavelength: Option<f64>) -> Option<Interior> {
+        Some(Interior {
+            priority: self.priority,
+            ior: self.ior_at(wavelength),
+            absorption: self.absorption,
+            scattering: Color::new(0.0, 0.0, 0.0),
+            g: 0.0,
+        })
+    }
+}
+
+pub struct RoughDielectric {
+    pub ir: f64,
+    pub absorption: Color,
+    pub distribution: TrowbridgeReitz,
+    pub priority: i32,
+}
+
+impl RoughDielectric {
+    pub fn new(ir: f64, roughness: f64) -> Self {
+        Self::with_absorption(ir, roughness, Color::new(0.0, 0.0, 0.0))
+    }
+
+    pub fn with_absorption(ir: f64, roughness: f64, absorption: Color) -> Self {
+        Self {
+            ir,
+            absorption,
+            distribution: TrowbridgeReitz::from_roughness(roughness),
+            priority: 0,
+        }
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        *attenuation = Color::new(1.0, 1.0, 1.0);
+        let eta = rec.transmitted_ior / rec.ior;
+
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-unit_vector(r_in.direction()));
+        if wo.z() <= 0.0 {
+            return false;
+        }
 
-        let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction = if cannot_refract || reflectance(cos_theta, refraction_ratio) > random_f64()
-        {
-            reflect(unit_direction, rec.normal)
+        let wm = self.distribution.sample_wm(wo, random_f64(), random_f64());
+        let fresnel = fresnel_dielectric(dot(wo, wm), eta);
+
+        // Choosing reflection with probability F cancels the Fresnel term, and visible-normal
+        // sampling leaves G2 / G1 as the weight for both lobes (Walter et al. 2007, Heitz 2014).
+        let wi = if random_f64() < fresnel {
+            let wi = reflect_local(wo, wm);
+            if wi.z() <= 0.0 {
+                return false;
+            }
+            wi
         } else {
-            refract(unit_direction, rec.normal, refraction_ratio)
+            match refract_local(wo, wm, eta) {
+                Some(wi) if wi.z() < 0.0 => wi,
+                _ => return false,
+            }
         };
 
-        *scattered = Ray::new(rec.p, direction);
+        *attenuation *= self.distribution.g(wo, wi) / self.distribution.g1(wo);
+        *scattered = Ray::new(rec.p, uvw.local(wi));
+        true
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let (f, _) = self.eval_pdf(rec, wo, wi);
+        Color::new(f, f, f)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        self.eval_pdf(rec, wo, wi).1
+    }
+
+    fn is_specular(&self) -> bool {
+        self.distribution.effectively_smooth()
+    }
+
+    fn interior(&self, _wavelength: Option<f64>) -> Option<Interior> {
+        Some(Interior {
+            priority: self.priority,
+            ior: self.ir,
+            absorption: self.absorption,
+            scattering: Color::new(0.0, 0.0, 0.0),
+            g: 0.0,
+        })
+    }
+}
+
+impl RoughDielectric {
+    // Returns (f * |cos_i|, pdf) using the generalized half vector for transmission.
+    fn eval_pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> (f64, f64) {
+        let eta = rec.transmitted_ior / rec.ior;
+        let uvw = Onb::build_from_w(rec.normal);
+        let (wo, wi) = (uvw.to_local(wo), uvw.to_local(wi));
+        let (cos_o, cos_i) = (wo.z(), wi.z());
+        if cos_o <= 0.0 || cos_i == 0.0 {
+            return (0.0, 0.0);
+        }
+
+        let reflect = cos_i > 0.0;
+        let wm = if reflect { wo + wi } else { eta * wi + wo };
+        if wm.length_squared() == 0.0 {
+            return (0.0, 0.0);
+        }
+        let wm = unit_vector(wm);
+        let wm = if wm.z() < 0.0 { -wm } else { wm };
+        if dot(wm, wi) * cos_i < 0.0 || dot(wm, wo) * cos_o < 0.0 {
+            return (0.0, 0.0);
+        }
+
+        let fresnel = fresnel_dielectric(dot(wo, wm), eta);
+        let d = self.distribution.d(wm);
+        let g = self.distribution.g(wo, wi);
+        if reflect {
+            let f = fresnel * d * g / (4.0 * cos_o);
+            let pdf = fresnel * self.distribution.pdf(wo, wm) / (4.0 * dot(wo, wm).abs());
+            (f, pdf)
+        } else {
+            let denom = (dot(wi, wm) + dot(wo, wm) / eta).powi(2);
+            let f = (1.0 - fresnel) * d * g * (dot(wi, wm) * dot(wo, wm)).abs() / (denom * cos_o);
+            let pdf = (1.0 - fresnel) * self.distribution.pdf(wo, wm) * dot(wi, wm).abs() / denom;
+            (f, pdf)
+        }
+    }
+}
+
+// Random-walk subsurface scattering for skin, wax, milk or marble. Light refracts through a
+// smooth dielectric boundary and scatters inside the closed shape until it leaves again; the
+// walk itself is sampled by `Scene::hit_object` from the interior on the path's medium stack.
+pub struct Subsurface {
+    pub ir: f64,
+    pub sigma_a: Color,
+    pub sigma_s: Color,
+    pub g: f64,
+    pub priority: i32,
+}
+
+impl Subsurface {
+    pub fn new(ir: f64, sigma_a: Color, sigma_s: Color, g: f64) -> Self {
+        Self {
+            ir,
+            sigma_a,
+            sigma_s,
+            g,
+            priority: 0,
+        }
+    }
+
+    // Coefficients from the colour the surface should have once light has scattered many
+    // times and the mean free path in each channel. The multiple-scattering albedo is mapped
+    // to a single-scattering one with the fit of Chiang et al., "Practical and Controllable
+    // Subsurface Scattering for Production Path Tracing".
+    pub fn from_albedo(ir: f64, albedo: Color, mean_free_path: Color, g: f64) -> Self {
+        let single_scattering = |a: f64| {
+            let a = a.clamp(0.0, 1.0);
+            let s = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+            1.0 - s * s
+        };
+        let mut sigma_a = Color::new(0.0, 0.0, 0.0);
+        let mut sigma_s = Color::new(0.0, 0.0, 0.0);
+        for c in 0..3 {
+            let sigma_t = 1.0 / mean_free_path[c].max(1e-6);
+            sigma_s[c] = single_scattering(albedo[c]) * sigma_t;
+            sigma_a[c] = sigma_t - sigma_s[c];
+        }
+        Self::new(ir, sigma_a, sigma_s, g)
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        *attenuation = Color::new(1.0, 1.0, 1.0);
+        *scattered = scatter_smooth_interface(r_in, rec);
+        true
+    }
+
+    fn interior(&self, _wavelength: Option<f64>) -> Option<Interior> {
+        Some(Interior {
+            priority: self.priority,
+            ior: self.ir,
+            absorption: self.sigma_a,
+            scattering: self.sigma_s,
+            g: self.g,
+        })
+    }
+}
+
+pub struct DiffuseLight {
+    pub emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _r_in: Ray,
+        _rec: &HitRecord,
+        _attenuation: &mut Vec3,
+        _scattered: &mut Ray,
+    ) -> bool {
+        false
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            self.emit
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
         true
     }
 }
 
+// Picks `b` with probability `amount` and `a` otherwise, independently for every hit, for
+// masks such as rust on metal or decals. A constant or texture amount reads the first channel.
+pub struct MixMaterial {
+    a: Arc<dyn Material>,
+    b: Arc<dyn Material>,
+    amount: Arc<dyn Texture>,
+}
+
+impl MixMaterial {
+    pub fn new<T: 'static + Texture>(
+        a: Arc<dyn Material>,
+        b: Arc<dyn Material>,
+        amount: T,
+    ) -> Self {
+        Self {
+            a,
+            b,
+            amount: Arc::new(amount),
+        }
+    }
+
+    fn amount(&self, rec: &HitRecord) -> f64 {
+        self.amount.value(rec.u, rec.v, rec.p).x().clamp(0.0, 1.0)
+    }
+}
+
+// Scenes resolve the mix when the ray hits, so the methods below are only called where it is
+// used directly, such as the base of a `Coated`. There it behaves as the blend of both.
+impl Material for MixMaterial {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        match self.choose(rec) {
+            Some(material) => material.scatter(r_in, rec, attenuation, scattered),
+            None => false,
+        }
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let amount = self.amount(rec);
+        (1.0 - amount) * self.a.eval(rec, wo, wi) + amount * self.b.eval(rec, wo, wi)
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let amount = self.amount(rec);
+        (1.0 - amount) * self.a.pdf(rec, wo, wi) + amount * self.b.pdf(rec, wo, wi)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.a.is_specular() && self.b.is_specular()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.a.is_dispersive() || self.b.is_dispersive()
+    }
+
+    fn choose(&self, rec: &HitRecord) -> Option<Arc<dyn Material>> {
+        if random_f64() < self.amount(rec) {
+            Some(Arc::clone(&self.b))
+        } else {
+            Some(Arc::clone(&self.a))
+        }
+    }
+}
+
+// Reflects or refracts off a smooth boundary between the media on either side of `rec`,
+// choosing by the Fresnel reflectance.
+fn scatter_smooth_interface(r_in: Ray, rec: &HitRecord) -> Ray {
+    let refraction_ratio = rec.ior / rec.transmitted_ior;
+
+    let unit_direction = unit_vector(r_in.direction());
+    let cos_theta = dot(-unit_direction, rec.normal).min(1.0);
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+    let cannot_refract = refraction_ratio * sin_theta > 1.0;
+    let direction = if cannot_refract || reflectance(cos_theta, refraction_ratio) > random_f64() {
+        reflect(unit_direction, rec.normal)
+    } else {
+        refract(unit_direction, rec.normal, refraction_ratio)
+    };
+
+    Ray::new(rec.p, direction)
+}
+
 fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
     let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
@@ -0,0 +1,422 @@
+use std::{
+    f64::consts::PI,
+    fs::File,
+    io::{self, BufReader, ErrorKind, Read},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Material, Shape, SHADOW_EPSILON},
+    material::Lambertian,
+    onb::Onb,
+    perlin::Perlin,
+    ray::Ray,
+    util::random_f64,
+    vec3::{dot, unit_vector, Color, Point3, Vec3},
+};
+
+// Spatially varying density of a participating medium. `max_density` must bound `density`
+// everywhere, it is the majorant for delta and ratio tracking.
+pub trait Density: Send + Sync {
+    fn density(&self, p: Point3) -> f64;
+
+    fn max_density(&self) -> f64;
+}
+
+pub struct UniformDensity {
+    density: f64,
+}
+
+impl UniformDensity {
+    pub fn new(density: f64) -> Self {
+        Self { density }
+    }
+}
+
+impl Density for UniformDensity {
+    fn density(&self, _p: Point3) -> f64 {
+        self.density
+    }
+
+    fn max_density(&self) -> f64 {
+        self.density
+    }
+}
+
+// Voxel densities spanning `bounds`, trilinearly interpolated between voxel centers and zero
+// outside the bounds.
+pub struct DensityGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    data: Vec<f64>,
+    bounds: Aabb,
+    max: f64,
+}
+
+impl DensityGrid {
+    // `data` is indexed by x fastest, then y, then z.
+    pub fn new(nx: usize, ny: usize, nz: usize, data: Vec<f64>, bounds: Aabb) -> Self {
+        assert_eq!(data.len(), nx * ny * nz, "density grid size mismatch");
+        let data: Vec<f64> = data.into_iter().map(|d| d.max(0.0)).collect();
+        let max = data.iter().copied().fold(0.0, f64::max);
+        Self {
+            nx,
+            ny,
+            nz,
+            data,
+            bounds,
+            max,
+        }
+    }
+
+    // Reads a grid file: the dimensions as three little-endian u32 values, followed by
+    // nx * ny * nz little-endian f32 densities in the order of `new`.
+    pub fn load<P: AsRef<Path>>(path: P, bounds: Aabb) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header)?;
+        let dim = |i: usize| {
+            u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]) as usize
+        };
+        let (nx, ny, nz) = (dim(0), dim(4), dim(8));
+        let count = voxel_count(nx, ny, nz, file_len.saturating_sub(header.len() as u64))?;
+        let data = read_f32s(&mut reader, count)?;
+        Ok(Self::new(nx, ny, nz, data, bounds))
+    }
+
+    // Reads headerless little-endian f32 densities for a grid of known size.
+    pub fn load_raw<P: AsRef<Path>>(
+        path: P,
+        nx: usize,
+        ny: usize,
+        nz: usize,
+        bounds: Aabb,
+    ) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let count = voxel_count(nx, ny, nz, file.metadata()?.len())?;
+        let data = read_f32s(&mut BufReader::new(file), count)?;
+        Ok(Self::new(nx, ny, nz, data, bounds))
+    }
+
+    fn voxel(&self, x: i64, y: i64, z: i64) -> f64 {
+        if x < 0
+            || y < 0
+            || z < 0
+            || x >= self.nx as i64
+            || y >= self.ny as i64
+            || z >= self.nz as i64
+        {
+            return 0.0;
+        }
+        self.data[(z as usize * self.ny + y as usize) * self.nx + x as usize]
+    }
+}
+
+// Number of voxels of an nx * ny * nz grid, checked against the `data_len` bytes of f32
+// densities a file holds for it before anything is allocated.
+fn voxel_count(nx: usize, ny: usize, nz: usize, data_len: u64) -> io::Result<usize> {
+    let count = nx.checked_mul(ny).and_then(|n| n.checked_mul(nz));
+    match count.and_then(|n| n.checked_mul(4)) {
+        Some(len) if len as u64 == data_len => Ok(count.unwrap()),
+        _ => Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "density grid size does not match the file",
+        )),
+    }
+}
+
+fn read_f32s<R: Read>(reader: &mut R, count: usize) -> io::Result<Vec<f64>> {
+    let mut bytes = vec![0u8; count * 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+        .collect())
+}
+
+impl Density for DensityGrid {
+    fn density(&self, p: Point3) -> f64 {
+        if self.data.is_empty() {
+            return 0.0;
+        }
+        let o = self.bounds.offset(p);
+        if o.x() < 0.0 || o.y() < 0.0 || o.z() < 0.0 || o.x() > 1.0 || o.y() > 1.0 || o.z() > 1.0 {
+            return 0.0;
+        }
+
+        // Continuous voxel coordinates with voxel centers at integers.
+        let gx = o.x() * self.nx as f64 - 0.5;
+        let gy = o.y() * self.ny as f64 - 0.5;
+        let gz = o.z() * self.nz as f64 - 0.5;
+        let (x, y, z) = (gx.floor(), gy.floor(), gz.floor());
+        let (dx, dy, dz) = (gx - x, gy - y, gz - z);
+        let (x, y, z) = (x as i64, y as i64, z as i64);
+
+        let lerp = |t: f64, a: f64, b: f64| (1.0 - t) * a + t * b;
+        let d00 = lerp(dx, self.voxel(x, y, z), self.voxel(x + 1, y, z));
+        let d10 = lerp(dx, self.voxel(x, y + 1, z), self.voxel(x + 1, y + 1, z));
+        let d01 = lerp(dx, self.voxel(x, y, z + 1), self.voxel(x + 1, y, z + 1));
+        let d11 = lerp(
+            dx,
+            self.voxel(x, y + 1, z + 1),
+            self.voxel(x + 1, y + 1, z + 1),
+        );
+        lerp(dz, lerp(dy, d00, d10), lerp(dy, d01, d11))
+    }
+
+    fn max_density(&self) -> f64 {
+        self.max
+    }
+}
+
+// Turbulent Perlin noise, for clouds and smoke without a grid.
+pub struct NoiseDensity {
+    noise: Perlin,
+    frequency: f64,
+    octaves: usize,
+    scale: f64,
+}
+
+impl NoiseDensity {
+    pub fn new(frequency: f64, octaves: usize, scale: f64) -> Self {
+        Self {
+            noise: Perlin::new(),
+            frequency,
+            octaves: octaves.max(1),
+            scale,
+        }
+    }
+}
+
+impl Density for NoiseDensity {
+    fn density(&self, p: Point3) -> f64 {
+        (self.scale * self.noise.turb(self.frequency * p, self.octaves)).min(self.max_density())
+    }
+
+    // The octave weights sum to less than two.
+    fn max_density(&self) -> f64 {
+        2.0 * self.scale
+    }
+}
+
+// A participating medium filling a closed, convex `boundary`. Its extinction coefficient is
+// `sigma_t` times the density. `hit` samples a real collision by delta tracking and reports it
+// with a zero normal; the material must be a phase function such as `HenyeyGreenstein`.
+pub struct Volume {
+    boundary: Arc<dyn Shape>,
+    density: Arc<dyn Density>,
+    sigma_t: f64,
+    // Only used to fill the records of boundary intersections.
+    boundary_material: Arc<dyn Material>,
+}
+
+impl Volume {
+    pub fn new<S: 'static + Shape, D: 'static + Density>(
+        boundary: S,
+        density: D,
+        sigma_t: f64,
+    ) -> Self {
+        Self {
+            boundary: Arc::new(boundary),
+            density: Arc::new(density),
+            sigma_t,
+            boundary_material: Arc::new(Lambertian::new(Color::new(0.0, 0.0, 0.0))),
+        }
+    }
+
+    // The part of `r` inside the boundary, clipped to [t_min, t_max].
+    fn interval(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let mut rec1 = HitRecord::new(Arc::clone(&self.boundary_material));
+        if !self
+            .boundary
+            .hit(*r, f64::NEG_INFINITY, f64::INFINITY, &mut rec1)
+        {
+            return None;
+        }
+        let mut rec2 = HitRecord::new(Arc::clone(&self.boundary_material));
+        if !self
+            .boundary
+            .hit(*r, rec1.t + 0.0001, f64::INFINITY, &mut rec2)
+        {
+            return None;
+        }
+
+        let t0 = rec1.t.max(t_min);
+        let t1 = rec2.t.min(t_max);
+        if t0 >= t1 {
+            return None;
+        }
+        Some((t0, t1))
+    }
+
+    fn majorant(&self) -> f64 {
+        self.sigma_t * self.density.max_density()
+    }
+
+    // Delta tracking: the first tentative collision accepted with probability density / max.
+    fn sample_collision(&self, r: &Ray, t0: f64, t1: f64) -> Option<f64> {
+        let majorant = self.majorant();
+        let speed = r.direction().length();
+        if majorant <= 0.0 || speed == 0.0 {
+            return None;
+        }
+
+        let max_density = self.density.max_density();
+        let mut t = t0;
+        loop {
+            t -= (1.0 - random_f64()).ln() / (majorant * speed);
+            if t >= t1 {
+                return None;
+            }
+            if random_f64() * max_density < self.density.density(r.at(t)) {
+                return Some(t);
+            }
+        }
+    }
+}
+
+impl Shape for Volume {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (t0, t1) = match self.interval(&r, t_min, t_max) {
+            Some(interval) => interval,
+            None => return false,
+        };
+        match self.sample_collision(&r, t0, t1) {
+            Some(t) => {
+                rec.t = t;
+                rec.p = r.at(t);
+                rec.normal = Vec3::new(0.0, 0.0, 0.0);
+                rec.front_face = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn occluded(&self, r: &Ray, t_max: f64) -> bool {
+        match self.interval(r, SHADOW_EPSILON, t_max) {
+            Some((t0, t1)) => self.sample_collision(r, t0, t1).is_some(),
+            None => false,
+        }
+    }
+
+    // Ratio tracking: the product of null-collision probabilities along the ray.
+    fn transmittance(&self, r: &Ray, t_max: f64) -> f64 {
+        let (t0, t1) = match self.interval(r, SHADOW_EPSILON, t_max) {
+            Some(interval) => interval,
+            None => return 1.0,
+        };
+        let majorant = self.majorant();
+        let speed = r.direction().length();
+        if majorant <= 0.0 || speed == 0.0 {
+            return 1.0;
+        }
+
+        let max_density = self.density.max_density();
+        let mut transmittance = 1.0;
+        let mut t = t0;
+        loop {
+            t -= (1.0 - random_f64()).ln() / (majorant * speed);
+            if t >= t1 {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density.density(r.at(t)) / max_density;
+
+            // Russian roulette once little light is left.
+            if transmittance < 0.1 {
+                if random_f64() < 0.5 {
+                    return 0.0;
+                }
+                transmittance *= 2.0;
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+// Henyey-Greenstein phase function for `Volume` collisions. `albedo` is the single-scattering
+// albedo; the absorbed remainder emits `emission`. Volumes are not registered as lights, so
+// only the unidirectional integrators account for their emission fully.
+pub struct HenyeyGreenstein {
+    albedo: Color,
+    g: f64,
+    emission: Color,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Color, g: f64) -> Self {
+        Self {
+            albedo,
+            g: g.clamp(-0.99, 0.99),
+            emission: Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn with_emission(albedo: Color, g: f64, emission: Color) -> Self {
+        Self {
+            emission,
+            ..Self::new(albedo, g)
+        }
+    }
+
+    // Density of scattering from `wo` into `wi`, both pointing away from the collision.
+    fn phase(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let cos_theta = dot(wo, wi);
+        let denom = 1.0 + self.g * self.g + 2.0 * self.g * cos_theta;
+        (1.0 - self.g * self.g) / (4.0 * PI * denom * denom.max(0.0).sqrt())
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        let wo = -unit_vector(r_in.direction());
+        let u = random_f64();
+        let cos_theta = if self.g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let sqr_term = (1.0 - self.g * self.g) / (1.0 + self.g - 2.0 * self.g * u);
+            -(1.0 + self.g * self.g - sqr_term * sqr_term) / (2.0 * self.g)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random_f64();
+        let wi = Onb::build_from_w(wo).local(Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+
+        *attenuation = self.albedo;
+        *scattered = Ray::new(rec.p, wi);
+        true
+    }
+
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        (Color::new(1.0, 1.0, 1.0) - self.albedo) * self.emission
+    }
+
+    fn eval(&self, _rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        self.albedo * self.phase(wo, wi)
+    }
+
+    fn pdf(&self, _rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        self.phase(wo, wi)
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{
+    util::random_f64,
+    vec3::{dot, Color, Vec3},
+};
+
+// Nested dielectrics with priorities (Schmidt and Budge, "Simple Nested Dielectrics in Ray
+// Traced Images"). Every path carries the stack of dielectric objects it is inside of, and
+// where objects overlap the one with the highest priority owns the volume. Boundaries of
+// lower priority objects inside it are false hits that only update the stack. Interiors that
+// scatter are sampled with a random walk, which is how `Subsurface` materials are rendered.
+
+// Deepest nesting tracked; objects entered beyond it are ignored.
+pub const MAX_NESTING: usize = 8;
+
+// The medium enclosed by a dielectric surface.
+#[derive(Debug, Clone, Copy)]
+pub struct Interior {
+    pub priority: i32,
+    pub ior: f64,
+    // Absorption and scattering coefficients per unit distance.
+    pub absorption: Color,
+    pub scattering: Color,
+    // Henyey-Greenstein asymmetry of the scattering.
+    pub g: f64,
+}
+
+impl Interior {
+    pub fn extinction(&self) -> Color {
+        self.absorption + self.scattering
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MediumStack {
+    entries: [(usize, Interior); MAX_NESTING],
+    len: usize,
+}
+
+impl MediumStack {
+    pub fn new() -> Self {
+        let vacuum = Interior {
+            priority: i32::MIN,
+            ior: 1.0,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            scattering: Color::new(0.0, 0.0, 0.0),
+            g: 0.0,
+        };
+        Self {
+            entries: [(usize::MAX, vacuum); MAX_NESTING],
+            len: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, object: usize) -> bool {
+        self.entries[..self.len].iter().any(|(o, _)| *o == object)
+    }
+
+    // The entry owning the current volume: the highest priority, the most recently entered
+    // one on ties.
+    pub fn top(&self) -> Option<(usize, Interior)> {
+        let mut top: Option<(usize, Interior)> = None;
+        for &(object, interior) in self.entries[..self.len].iter() {
+            if top.is_none_or(|(_, t)| interior.priority >= t.priority) {
+                top = Some((object, interior));
+            }
+        }
+        top
+    }
+
+    pub fn with(&self, object: usize, interior: Interior) -> Self {
+        let mut stack = *self;
+        if !stack.contains(object) && stack.len < MAX_NESTING {
+            stack.entries[stack.len] = (object, interior);
+            stack.len += 1;
+        }
+        stack
+    }
+
+    pub fn without(&self, object: usize) -> Self {
+        let mut stack = *self;
+        if let Some(i) = stack.entries[..stack.len]
+            .iter()
+            .position(|(o, _)| *o == object)
+        {
+            stack.entries.copy_within(i + 1..stack.len, i);
+            stack.len -= 1;
+        }
+        stack
+    }
+
+    // Index of refraction of the current volume, 1 outside every object.
+    pub fn ior(&self) -> f64 {
+        self.top().map_or(1.0, |(_, interior)| interior.ior)
+    }
+
+    // Fraction of light surviving `distance` through the current volume.
+    pub fn transmittance(&self, distance: f64) -> Color {
+        match self.top() {
+            Some((_, interior)) if interior.extinction().length_squared() > 0.0 => {
+                (-distance * interior.extinction()).exp()
+            }
+            _ => Color::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    // Samples where light first scatters within `distance` through the current volume.
+    // Returns the collision distance, if there is one, and the path weight up to that point.
+    //
+    // The distance is sampled with the extinction of one channel picked at random and weighted
+    // by the average density over all channels (spectral MIS, as in pbrt-v3), so coloured
+    // media do not add fireflies. Dense, strongly coloured media still converge slowly.
+    pub fn sample_scattering(&self, distance: f64) -> (Option<f64>, Color) {
+        let interior = match self.top() {
+            Some((_, interior)) if interior.scattering.length_squared() > 0.0 => interior,
+            _ => return (None, self.transmittance(distance)),
+        };
+        let sigma_t = interior.extinction();
+        let channel = ((random_f64() * 3.0) as usize).min(2);
+        let t = if sigma_t[channel] > 0.0 {
+            -(1.0 - random_f64()).ln() / sigma_t[channel]
+        } else {
+            f64::INFINITY
+        };
+
+        if t < distance {
+            let tr = (-t * sigma_t).exp();
+            let density = sigma_t * tr;
+            let pdf = (density.x() + density.y() + density.z()) / 3.0;
+            if pdf <= 0.0 {
+                return (None, Color::new(0.0, 0.0, 0.0));
+            }
+            (Some(t), tr * interior.scattering / pdf)
+        } else {
+            let tr = (-distance * sigma_t).exp();
+            let pdf = (tr.x() + tr.y() + tr.z()) / 3.0;
+            if pdf <= 0.0 {
+                return (None, Color::new(0.0, 0.0, 0.0));
+            }
+            (None, tr / pdf)
+        }
+    }
+}
+
+impl Default for MediumStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Media on the side a ray arrived at a surface from and on its far side, returned beside the
+// record of the hit. They only differ at the boundary of a dielectric interior.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HitMedia {
+    pub incident: MediumStack,
+    pub transmitted: MediumStack,
+}
+
+impl HitMedia {
+    // Media a ray leaving the hit point along `direction` travels through, with `normal` facing
+    // the incident side.
+    pub fn leaving(&self, normal: Vec3, direction: Vec3) -> &MediumStack {
+        if dot(direction, normal) < 0.0 {
+            &self.transmitted
+        } else {
+            &self.incident
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use crate::vec3::{cross, dot, unit_vector, Vec3};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn build_from_w(n: Vec3) -> Self {
+        let w = unit_vector(n);
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = unit_vector(cross(w, a));
+        let u = cross(w, v);
+        Self { u, v, w }
+    }
+
+    // Like `build_from_w`, with cross(u, v) = w, for surfaces parameterized by the angle
+    // around an axis.
+    pub fn build_right_handed(n: Vec3) -> Self {
+        let uvw = Self::build_from_w(n);
+        Self {
+            u: uvw.v,
+            v: uvw.u,
+            w: uvw.w,
+        }
+    }
+
+    // Frame around `n` with u along `tangent` projected onto the surface, for anisotropic
+    // materials. Falls back to an arbitrary frame when the tangent is zero or along `n`.
+    pub fn build_from_w_and_tangent(n: Vec3, tangent: Vec3) -> Self {
+        let w = unit_vector(n);
+        let u = tangent - dot(tangent, w) * w;
+        if u.length_squared() <= 1e-12 * tangent.length_squared() {
+            return Self::build_from_w(n);
+        }
+        let u = unit_vector(u);
+        Self {
+            u,
+            v: cross(w, u),
+            w,
+        }
+    }
+
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(dot(a, self.u), dot(a, self.v), dot(a, self.w))
+    }
+}
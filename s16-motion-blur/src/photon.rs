@@ -0,0 +1,258 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    hittable::HitRecord,
+    kdtree::KdTree,
+    medium_stack::{HitMedia, MediumStack},
+    ray::Ray,
+    scene::Scene,
+    util::random_f64,
+    vec3::{dot, unit_vector, Color, Point3, Vec3},
+};
+
+// Two-pass photon mapping (Jensen) made progressive by shrinking the gather radius after each
+// pass (Knaus and Zwicker, "Progressive Photon Mapping: A Probabilistic Approach").
+//
+// Photons are emitted from the scene lights only. Direct illumination is computed with shadow
+// rays, paths of the form L S+ D come from the caustic map and everything with at least one
+// non-specular bounce from the global map. The background does not emit photons, so it is
+// gathered by continuing the camera path and only counting rays that escape; its light, if
+// it has one, is a scene light like any other. Camera paths
+// pass through media, with a shadow ray at every collision, and gather at the first surface.
+
+pub struct Photon {
+    // Unit direction the photon arrived from.
+    pub wi: Vec3,
+    pub n: Vec3,
+    pub power: Color,
+}
+
+pub struct PhotonMaps {
+    caustic: KdTree<Photon>,
+    global: KdTree<Photon>,
+    n_emitted: usize,
+    radius: f64,
+}
+
+enum PhotonKind {
+    Caustic,
+    Global,
+}
+
+impl PhotonMaps {
+    pub fn build(scene: &Scene, n_photons: usize, radius: f64, max_depth: i32) -> Self {
+        let photons: Vec<(PhotonKind, Point3, Photon)> = (0..n_photons)
+            .into_par_iter()
+            .flat_map_iter(|_| trace_photon(scene, max_depth))
+            .collect();
+
+        let mut caustic = Vec::new();
+        let mut global = Vec::new();
+        for (kind, p, photon) in photons {
+            match kind {
+                PhotonKind::Caustic => caustic.push((p, photon)),
+                PhotonKind::Global => global.push((p, photon)),
+            }
+        }
+
+        Self {
+            caustic: KdTree::build(caustic),
+            global: KdTree::build(global),
+            n_emitted: n_photons,
+            radius,
+        }
+    }
+
+    // r_i^2 = r_0^2 * prod_{k=1..i} (k + alpha) / (k + 1), Knaus and Zwicker's schedule with
+    // passes counted from zero.
+    pub fn radius_for_pass(initial_radius: f64, alpha: f64, pass: usize) -> f64 {
+        let mut radius_squared = initial_radius * initial_radius;
+        for k in 1..=pass {
+            radius_squared *= (k as f64 + alpha) / (k as f64 + 1.0);
+        }
+        radius_squared.sqrt()
+    }
+
+    pub fn caustic_photons(&self) -> usize {
+        self.caustic.len()
+    }
+
+    pub fn global_photons(&self) -> usize {
+        self.global.len()
+    }
+
+    pub fn radiance(&self, rec: &HitRecord, wo: Vec3) -> Color {
+        self.estimate(&self.caustic, rec, wo) + self.estimate(&self.global, rec, wo)
+    }
+
+    fn estimate(&self, map: &KdTree<Photon>, rec: &HitRecord, wo: Vec3) -> Color {
+        if map.is_empty() || self.n_emitted == 0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        map.for_each_within(rec.p, self.radius, |photon, _| {
+            let cos_theta = dot(photon.wi, rec.normal);
+            if dot(photon.n, rec.normal) <= 0.0 || cos_theta <= 0.0 {
+                return;
+            }
+            let f = rec.material.eval(rec, wo, photon.wi) / cos_theta;
+            sum += f * photon.power;
+        });
+        sum / (PI * self.radius * self.radius * self.n_emitted as f64)
+    }
+}
+
+fn trace_photon(scene: &Scene, max_depth: i32) -> Vec<(PhotonKind, Point3, Photon)> {
+    let mut stored = Vec::new();
+    let (light_index, light_pmf) = match scene.sample_emitting_light() {
+        Some(sampled) => sampled,
+        None => return stored,
+    };
+    let es = match scene.lights[light_index].sample_le() {
+        Some(es) if es.pdf_pos > 0.0 && es.pdf_dir > 0.0 => es,
+        _ => return stored,
+    };
+    let cos_theta = if es.normal.length_squared() > 0.0 {
+        dot(es.normal, unit_vector(es.ray.direction())).abs()
+    } else {
+        1.0
+    };
+    let mut power = es.radiance * (cos_theta / (light_pmf * es.pdf_pos * es.pdf_dir));
+
+    let mut ray = es.ray;
+    let mut specular_path = false;
+    let mut diffuse_path = false;
+    let mut media = MediumStack::new();
+
+    for _ in 0..max_depth {
+        let (rec, hit_media) = match scene.hit(ray, &media, 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => break,
+        };
+        let material = Arc::clone(&rec.material);
+        power = power * rec.transmittance;
+
+        // Medium collisions have no normal, photons are only gathered on surfaces.
+        let on_surface = rec.normal.length_squared() > 0.0;
+        if on_surface && !material.is_specular() && (specular_path || diffuse_path) {
+            let kind = if diffuse_path {
+                PhotonKind::Global
+            } else {
+                PhotonKind::Caustic
+            };
+            let photon = Photon {
+                wi: -unit_vector(ray.direction()),
+                n: rec.normal,
+                power,
+            };
+            stored.push((kind, rec.p, photon));
+        }
+
+        let mut scattered = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        let mut attenuation = Color::new(0.0, 0.0, 0.0);
+        if !material.scatter(ray, &rec, &mut attenuation, &mut scattered) {
+            break;
+        }
+
+        // Russian roulette keeps photon powers roughly constant.
+        let q = attenuation
+            .x()
+            .max(attenuation.y())
+            .max(attenuation.z())
+            .min(1.0);
+        if q <= 0.0 || random_f64() > q {
+            break;
+        }
+        power = power * attenuation / q;
+
+        if material.is_specular() {
+            specular_path = true;
+        } else {
+            diffuse_path = true;
+        }
+        ray = scattered;
+        media = *hit_media.leaving(rec.normal, scattered.direction());
+    }
+
+    stored
+}
+
+pub fn ray_color_photon(r: Ray, scene: &Scene, maps: &PhotonMaps, max_depth: i32) -> Color {
+    let mut l = Color::new(0.0, 0.0, 0.0);
+    let mut beta = Color::new(1.0, 1.0, 1.0);
+    let mut ray = r;
+    let mut gathered = false;
+    // Cleared once a shadow ray has accounted for the lights seen from a medium collision.
+    let mut count_lights = true;
+    let mut media = MediumStack::new();
+
+    for _ in 0..max_depth {
+        let (object, rec, hit_media) = match scene.hit_object(ray, &media, 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => {
+                l += beta * scene.background.value(ray);
+                // Lights at infinity are sampled by the shadow rays and emit photons, like
+                // the area lights.
+                if !gathered && count_lights {
+                    for &light in scene.infinite_lights() {
+                        l += beta * scene.lights[light].le(&ray);
+                    }
+                }
+                break;
+            }
+        };
+        let material = Arc::clone(&rec.material);
+        let wo = -unit_vector(ray.direction());
+        beta = beta * rec.transmittance;
+
+        if !gathered {
+            if count_lights || scene.area_light(object).is_none() {
+                l += beta * material.emitted(&rec);
+            }
+            if !material.is_specular() {
+                l += beta * direct_lighting(scene, &rec, &hit_media, wo);
+                if rec.normal.length_squared() > 0.0 {
+                    l += beta * maps.radiance(&rec, wo);
+                    gathered = true;
+                } else {
+                    count_lights = false;
+                }
+            }
+        }
+
+        let mut scattered = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        let mut attenuation = Color::new(0.0, 0.0, 0.0);
+        if !material.scatter(ray, &rec, &mut attenuation, &mut scattered) {
+            break;
+        }
+        beta = beta * attenuation;
+        ray = scattered;
+        media = *hit_media.leaving(rec.normal, scattered.direction());
+    }
+
+    l
+}
+
+fn direct_lighting(scene: &Scene, rec: &HitRecord, hit_media: &HitMedia, wo: Vec3) -> Color {
+    let (light_index, light_pmf) = match scene.sample_light(rec.p, rec.normal) {
+        Some(sampled) => sampled,
+        None => return Color::new(0.0, 0.0, 0.0),
+    };
+    let ls = match scene.lights[light_index].sample_li(rec.p) {
+        Some(ls) if ls.pdf > 0.0 => ls,
+        _ => return Color::new(0.0, 0.0, 0.0),
+    };
+
+    let f = rec.material.eval(rec, wo, ls.wi);
+    if f.length_squared() == 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    let tr = scene.segment_transmittance(rec.p, ls.wi, ls.distance)
+        * hit_media
+            .leaving(rec.normal, ls.wi)
+            .transmittance(ls.distance);
+    f * ls.radiance * tr / (light_pmf * ls.pdf)
+}
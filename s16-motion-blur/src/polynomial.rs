@@ -0,0 +1,366 @@
+use std::f64::consts::PI;
+
+// Real roots of polynomials, for intersecting surfaces: in closed form up to degree four, by
+// Sturm sequences for any degree. Coefficients are in ascending order of power, and roots are
+// returned sorted.
+
+// Value of the polynomial `c` at `x`, by Horner's rule.
+pub fn evaluate(c: &[f64], x: f64) -> f64 {
+    c.iter().rev().fold(0.0, |acc, &ci| acc * x + ci)
+}
+
+// Roots of c0 + c1 x + c2 x^2, avoiding the cancellation of the textbook formula.
+pub fn solve_quadratic(c0: f64, c1: f64, c2: f64) -> Vec<f64> {
+    if c2 == 0.0 {
+        if c1 == 0.0 {
+            return Vec::new();
+        }
+        return vec![-c0 / c1];
+    }
+    let discriminant = c1 * c1 - 4.0 * c2 * c0;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+    let q = -0.5 * (c1 + discriminant.sqrt().copysign(c1));
+    if q == 0.0 {
+        return vec![0.0, 0.0];
+    }
+    let (a, b) = (q / c2, c0 / q);
+    if a < b {
+        vec![a, b]
+    } else {
+        vec![b, a]
+    }
+}
+
+// Roots of c0 + c1 x + c2 x^2 + c3 x^3 (Cardano, or trigonometric with three real roots).
+pub fn solve_cubic(c0: f64, c1: f64, c2: f64, c3: f64) -> Vec<f64> {
+    if c3 == 0.0 {
+        return solve_quadratic(c0, c1, c2);
+    }
+    let (a, b, c) = (c2 / c3, c1 / c3, c0 / c3);
+    // x = y - a / 3 leaves y^3 + p y + q.
+    let p = b - a * a / 3.0;
+    let q = 2.0 * a * a * a / 27.0 - a * b / 3.0 + c;
+    let shift = -a / 3.0;
+
+    let discriminant = 0.25 * q * q + p * p * p / 27.0;
+    // Rounding can leave the discriminant of a double root slightly positive, which would
+    // lose the root, so anything within rounding of zero counts as three real roots.
+    let tolerance = 1e-12 * (0.25 * q * q + (p * p * p / 27.0).abs());
+    let mut roots = if p == 0.0 {
+        vec![(-q).cbrt()]
+    } else if discriminant > tolerance {
+        let s = discriminant.sqrt();
+        vec![(-0.5 * q + s).cbrt() + (-0.5 * q - s).cbrt()]
+    } else {
+        let r = (-p / 3.0).sqrt();
+        let phi = (-q / (2.0 * r * r * r)).clamp(-1.0, 1.0).acos();
+        (0..3)
+            .map(|k| 2.0 * r * ((phi + 2.0 * PI * k as f64) / 3.0).cos())
+            .collect()
+    };
+    for y in roots.iter_mut() {
+        *y += shift;
+    }
+    polish(&[c0, c1, c2, c3], roots)
+}
+
+// Roots of c0 + c1 x + c2 x^2 + c3 x^3 + c4 x^4 (Ferrari).
+pub fn solve_quartic(c0: f64, c1: f64, c2: f64, c3: f64, c4: f64) -> Vec<f64> {
+    if c4 == 0.0 {
+        return solve_cubic(c0, c1, c2, c3);
+    }
+    let (a, b, c, d) = (c3 / c4, c2 / c4, c1 / c4, c0 / c4);
+    // x = y - a / 4 leaves y^4 + p y^2 + q y + r.
+    let a2 = a * a;
+    let p = b - 0.375 * a2;
+    let q = c - 0.5 * a * b + 0.125 * a2 * a;
+    let r = d - 0.25 * a * c + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+    let shift = -0.25 * a;
+
+    let mut roots = Vec::with_capacity(4);
+    if q.abs() < 1e-12 * (1.0 + p.abs() + r.abs()) {
+        // Quadratic in y^2.
+        for z in solve_quadratic(r, p, 1.0) {
+            if z >= 0.0 {
+                let y = z.sqrt();
+                roots.push(-y);
+                roots.push(y);
+            }
+        }
+    } else {
+        // (y^2 + p / 2 + m)^2 is then a perfect square 2 m (y - q / (4 m))^2 for a positive
+        // root m of the resolvent cubic.
+        let m = solve_cubic(-q * q / 8.0, 0.25 * p * p - r, p, 1.0)
+            .into_iter()
+            .fold(f64::NEG_INFINITY, f64::max);
+        if m <= 0.0 {
+            return Vec::new();
+        }
+        let s = (2.0 * m).sqrt();
+        roots.extend(solve_quadratic(0.5 * p + m + q / (2.0 * s), -s, 1.0));
+        roots.extend(solve_quadratic(0.5 * p + m - q / (2.0 * s), s, 1.0));
+    }
+    for y in roots.iter_mut() {
+        *y += shift;
+    }
+    polish(&[c0, c1, c2, c3, c4], roots)
+}
+
+// Refines closed form roots of `c` with a few Newton steps, which recover the precision lost
+// to cancellation, and sorts them.
+fn polish(c: &[f64], mut roots: Vec<f64>) -> Vec<f64> {
+    let derivative: Vec<f64> = c
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, ci)| i as f64 * ci)
+        .collect();
+    for x in roots.iter_mut() {
+        let mut value = evaluate(c, *x);
+        for _ in 0..3 {
+            let next = *x - value / evaluate(&derivative, *x);
+            let next_value = evaluate(c, next);
+            // Steps near multiple roots can overshoot to a neighbouring one.
+            if next_value.is_nan() || next_value.abs() >= value.abs() {
+                break;
+            }
+            (*x, value) = (next, next_value);
+        }
+    }
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+// Sturm sequence of a polynomial of any degree: the polynomial, its derivative, then the
+// negated remainders of dividing each by the next. The number of sign changes along the
+// sequence drops by one at every distinct real root.
+pub struct SturmSequence {
+    polynomials: Vec<Vec<f64>>,
+}
+
+impl SturmSequence {
+    pub fn new(c: &[f64]) -> Self {
+        let p0 = trimmed(c);
+        let p1 = trimmed(
+            &p0.iter()
+                .enumerate()
+                .skip(1)
+                .map(|(i, ci)| i as f64 * ci)
+                .collect::<Vec<f64>>(),
+        );
+        let mut polynomials = vec![p0, p1];
+        while polynomials[polynomials.len() - 1].len() > 1 {
+            let n = polynomials.len();
+            let remainder = remainder(&polynomials[n - 2], &polynomials[n - 1]);
+            if remainder.is_empty() {
+                break;
+            }
+            polynomials.push(remainder.iter().map(|c| -c).collect());
+        }
+        Self { polynomials }
+    }
+
+    fn sign_changes(&self, x: f64) -> usize {
+        let mut changes = 0;
+        let mut last = 0.0;
+        for p in self.polynomials.iter() {
+            let value = evaluate(p, x);
+            if value != 0.0 {
+                if last * value < 0.0 {
+                    changes += 1;
+                }
+                last = value;
+            }
+        }
+        changes
+    }
+
+    // Number of distinct real roots in (a, b].
+    pub fn count(&self, a: f64, b: f64) -> usize {
+        self.sign_changes(a).saturating_sub(self.sign_changes(b))
+    }
+
+    // The smallest root in (a, b), isolated by bisecting with root counts and refined by
+    // bisecting on sign changes.
+    pub fn first_root(&self, a: f64, b: f64) -> Option<f64> {
+        self.first_root_within(a, b, 0)
+    }
+
+    fn first_root_within(&self, a: f64, b: f64, depth: u32) -> Option<f64> {
+        let count = self.count(a, b);
+        if count == 0 {
+            return None;
+        }
+        let mid = 0.5 * (a + b);
+        if count == 1 || depth >= MAX_BISECTIONS || mid <= a || mid >= b {
+            let p = &self.polynomials[0];
+            if evaluate(p, a) * evaluate(p, b) < 0.0 {
+                return Some(bisect(p, a, b));
+            }
+            // A root of even multiplicity, which only the counts can find.
+            if depth >= MAX_BISECTIONS || mid <= a || mid >= b {
+                return Some(mid);
+            }
+        }
+        self.first_root_within(a, mid, depth + 1)
+            .or_else(|| self.first_root_within(mid, b, depth + 1))
+    }
+}
+
+const MAX_BISECTIONS: u32 = 80;
+
+// The root of `c` in (a, b), whose ends it has opposite signs at.
+fn bisect(c: &[f64], mut a: f64, mut b: f64) -> f64 {
+    let mut value_a = evaluate(c, a);
+    for _ in 0..MAX_BISECTIONS {
+        let mid = 0.5 * (a + b);
+        if mid <= a || mid >= b {
+            break;
+        }
+        let value = evaluate(c, mid);
+        if value == 0.0 {
+            return mid;
+        }
+        if value * value_a < 0.0 {
+            b = mid;
+        } else {
+            (a, value_a) = (mid, value);
+        }
+    }
+    0.5 * (a + b)
+}
+
+// `c` without leading coefficients that are negligible next to the others.
+fn trimmed(c: &[f64]) -> Vec<f64> {
+    let scale = c.iter().fold(0.0, |m: f64, ci| m.max(ci.abs()));
+    let mut c = c.to_vec();
+    while c.last().is_some_and(|ci| ci.abs() <= 1e-12 * scale) {
+        c.pop();
+    }
+    c
+}
+
+// Remainder of dividing `a` by `b`, trimmed.
+fn remainder(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut r = a.to_vec();
+    let lead = b[b.len() - 1];
+    while r.len() >= b.len() {
+        let factor = r[r.len() - 1] / lead;
+        let offset = r.len() - b.len();
+        for (i, bi) in b.iter().enumerate() {
+            r[offset + i] -= factor * bi;
+        }
+        r.pop();
+    }
+    let scale = a.iter().fold(0.0, |m: f64, ci| m.max(ci.abs()));
+    if r.iter().all(|ri| ri.abs() <= 1e-12 * scale) {
+        return Vec::new();
+    }
+    trimmed(&r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roots(roots: &[f64], expected: &[f64], tolerance: f64) {
+        assert_eq!(
+            roots.len(),
+            expected.len(),
+            "roots {roots:?}, expected {expected:?}"
+        );
+        for (root, expected) in roots.iter().zip(expected.iter()) {
+            assert!(
+                (root - expected).abs() < tolerance,
+                "roots {roots:?}, expected {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn quadratic_double_root() {
+        // (x - 2)^2
+        assert_roots(&solve_quadratic(4.0, -4.0, 1.0), &[2.0, 2.0], 1e-12);
+    }
+
+    #[test]
+    fn cubic_without_linear_term_after_shift() {
+        // (x - 1)^3 and x^3 - 8 both reduce to y^3 + q with p == 0.
+        assert_roots(&solve_cubic(-1.0, 3.0, -3.0, 1.0), &[1.0], 1e-12);
+        assert_roots(&solve_cubic(-8.0, 0.0, 0.0, 1.0), &[2.0], 1e-12);
+    }
+
+    #[test]
+    fn cubic_double_root() {
+        // (x - 1)^2 (x - 3): rounding must not lose the double root, nor
+        // Newton steps near it run off to 3.
+        assert_roots(&solve_cubic(-3.0, 7.0, -5.0, 1.0), &[1.0, 1.0, 3.0], 1e-6);
+    }
+
+    #[test]
+    fn quartic_distinct_roots() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        let roots = solve_quartic(24.0, -50.0, 35.0, -10.0, 1.0);
+        assert_roots(&roots, &[1.0, 2.0, 3.0, 4.0], 1e-10);
+    }
+
+    #[test]
+    fn quartic_biquadratic() {
+        // x^4 - 5 x^2 + 4 has q == 0 after the shift.
+        let roots = solve_quartic(4.0, 0.0, -5.0, 0.0, 1.0);
+        assert_roots(&roots, &[-2.0, -1.0, 1.0, 2.0], 1e-12);
+    }
+
+    #[test]
+    fn quartic_tangent_torus_ray() {
+        // A torus with radii 1 and 0.3 about z, grazed along its top by the ray from
+        // (-3, 0, 0.3) along x: (x^2 + y^2 + z^2 + R^2 - r^2)^2 - 4 R^2 (x^2 + y^2) becomes
+        // ((t - 3)^2 - 1)^2, touching at t = 2 and t = 4.
+        let roots = solve_quartic(64.0, -96.0, 52.0, -12.0, 1.0);
+        assert!(!roots.is_empty());
+        assert!(roots
+            .iter()
+            .all(|t| (t - 2.0).abs() < 1e-6 || (t - 4.0).abs() < 1e-6));
+        assert!(roots.iter().any(|t| (t - 2.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn sturm_counts_distinct_roots() {
+        // (x - 1)(x - 2)^2 (x - 3)(x + 5)
+        let sturm = SturmSequence::new(&[60.0, -128.0, 87.0, -17.0, -3.0, 1.0]);
+        assert_eq!(sturm.count(-10.0, 10.0), 4);
+        assert_eq!(sturm.count(1.5, 2.5), 1);
+        assert_eq!(sturm.count(3.5, 10.0), 0);
+        // x^2 + 1
+        assert_eq!(SturmSequence::new(&[1.0, 0.0, 1.0]).count(-10.0, 10.0), 0);
+    }
+
+    #[test]
+    fn sturm_first_root() {
+        let sturm = SturmSequence::new(&[60.0, -128.0, 87.0, -17.0, -3.0, 1.0]);
+        let first = |a, b| sturm.first_root(a, b).unwrap();
+        assert!((first(-10.0, 10.0) + 5.0).abs() < 1e-10);
+        assert!((first(0.0, 10.0) - 1.0).abs() < 1e-10);
+        assert!((first(2.5, 10.0) - 3.0).abs() < 1e-10);
+        assert_eq!(sturm.first_root(3.5, 10.0), None);
+    }
+
+    #[test]
+    fn sturm_even_multiplicity_root() {
+        // The polynomial keeps its sign across the double root at 2.
+        let sturm = SturmSequence::new(&[60.0, -128.0, 87.0, -17.0, -3.0, 1.0]);
+        let root = sturm.first_root(1.5, 10.0).unwrap();
+        assert!((root - 2.0).abs() < 1e-6, "root {root}");
+    }
+
+    #[test]
+    fn sturm_tangent_torus_ray() {
+        // The grazing ray of `quartic_tangent_torus_ray`, whose roots are both double.
+        let sturm = SturmSequence::new(&[64.0, -96.0, 52.0, -12.0, 1.0]);
+        assert_eq!(sturm.count(0.0, 10.0), 2);
+        let root = sturm.first_root(0.0, 10.0).unwrap();
+        assert!((root - 2.0).abs() < 1e-6, "root {root}");
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Shape, SHADOW_EPSILON},
+    ray::Ray,
+    util::random_f64,
+    vec3::{cross, dot, unit_vector, Point3, Vec3},
+};
+
+// Parallelogram with corner `q` and edges `u` and `v`. Its front face points along
+// cross(u, v), and (u, v) surface coordinates run from 0 to 1 along the edges.
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3,
+    // Projects a point in the plane onto the edges.
+    w: Vec3,
+    area: f64,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3) -> Self {
+        let n = cross(u, v);
+        Self {
+            q,
+            u,
+            v,
+            normal: unit_vector(n),
+            w: n / dot(n, n),
+            area: n.length(),
+        }
+    }
+
+    // Ray parameter and surface coordinates of the intersection with `r` in (t_min, t_max).
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let denom = dot(self.normal, r.direction());
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = dot(self.normal, self.q - r.origin()) / denom;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        let planar = r.at(t) - self.q;
+        let alpha = dot(self.w, cross(planar, self.v));
+        let beta = dot(self.w, cross(self.u, planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        Some((t, alpha, beta))
+    }
+}
+
+impl Shape for Quad {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        match self.intersect(&r, t_min, t_max) {
+            Some((t, alpha, beta)) => {
+                rec.t = t;
+                rec.p = r.at(t);
+                rec.set_face_normal(r, self.normal);
+                (rec.u, rec.v) = (alpha, beta);
+                (rec.dpdu, rec.dpdv) = (self.u, self.v);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn occluded(&self, r: &Ray, t_max: f64) -> bool {
+        self.intersect(r, SHADOW_EPSILON, t_max).is_some()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let r = Ray::new(origin, direction);
+        let t = match self.intersect(&r, SHADOW_EPSILON, f64::INFINITY) {
+            Some((t, _, _)) => t,
+            None => return 0.0,
+        };
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = dot(direction, self.normal).abs() / direction.length();
+        if cosine == 0.0 {
+            return 0.0;
+        }
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        self.q + random_f64() * self.u + random_f64() * self.v - origin
+    }
+
+    fn area(&self) -> f64 {
+        self.area
+    }
+
+    fn sample_area(&self) -> Option<(Point3, Vec3)> {
+        let p = self.q + random_f64() * self.u + random_f64() * self.v;
+        Some((p, self.normal))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let far = self.q + self.u + self.v;
+        Some(
+            Aabb::new(self.q, far)
+                .union_point(self.q + self.u)
+                .union_point(self.q + self.v),
+        )
+    }
+}
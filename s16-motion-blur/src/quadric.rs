@@ -0,0 +1,161 @@
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Shape, SHADOW_EPSILON},
+    polynomial::solve_quadratic,
+    ray::Ray,
+    vec3::{unit_vector, Point3, Vec3},
+};
+
+// The surface p^T Q p = 0 of a symmetric 4x4 matrix Q, with p = (x, y, z, 1) in homogeneous
+// coordinates, clipped to a box if it has bounds. The outside is where p^T Q p is positive.
+// Quadrics have no parameterization, so u and v are zero and only solid textures vary on them.
+pub struct Quadric {
+    matrix: [[f64; 4]; 4],
+    bounds: Option<Aabb>,
+}
+
+impl Quadric {
+    pub fn new(matrix: [[f64; 4]; 4]) -> Self {
+        Self {
+            matrix,
+            bounds: None,
+        }
+    }
+
+    pub fn with_bounds(mut self, bounds: Aabb) -> Self {
+        self.bounds = Some(bounds);
+        self
+    }
+
+    // x^2 / a^2 + y^2 / b^2 + z^2 / c^2 = 1 about `center`, for the `radii` (a, b, c).
+    pub fn ellipsoid(center: Point3, radii: Vec3) -> Self {
+        let (a, b, c) = (radii.x(), radii.y(), radii.z());
+        let matrix = diagonal(1.0 / (a * a), 1.0 / (b * b), 1.0 / (c * c), -1.0);
+        Self::new(translated(matrix, center)).with_bounds(Aabb::new(center - radii, center + radii))
+    }
+
+    // The hyperboloid of one sheet x^2 / a^2 + y^2 / b^2 - z^2 / c^2 = 1 about `center`, with
+    // its waist of radii a and b and its axis along z, cut to `height` about the waist.
+    pub fn hyperboloid(center: Point3, radii: Vec3, height: f64) -> Self {
+        let (a, b, c) = (radii.x(), radii.y(), radii.z());
+        let matrix = diagonal(1.0 / (a * a), 1.0 / (b * b), -1.0 / (c * c), -1.0);
+        let half_height = 0.5 * height;
+        let flare = (1.0 + half_height * half_height / (c * c)).sqrt();
+        let extent = Vec3::new(a * flare, b * flare, half_height);
+        Self::new(translated(matrix, center))
+            .with_bounds(Aabb::new(center - extent, center + extent))
+    }
+
+    // The paraboloid z = h (x^2 / a^2 + y^2 / b^2) with its tip at `vertex`, opening up along z
+    // to a rim of radii a and b at `height` h.
+    pub fn paraboloid(vertex: Point3, radii: Vec3, height: f64) -> Self {
+        let (a, b) = (radii.x(), radii.y());
+        let mut matrix = diagonal(1.0 / (a * a), 1.0 / (b * b), 0.0, 0.0);
+        matrix[2][3] = -0.5 / height;
+        matrix[3][2] = -0.5 / height;
+        let bounds = Aabb::new(
+            vertex - Vec3::new(a, b, 0.0),
+            vertex + Vec3::new(a, b, height),
+        );
+        Self::new(translated(matrix, vertex)).with_bounds(bounds)
+    }
+
+    // a^T Q b for homogeneous vectors.
+    fn form(&self, a: [f64; 4], b: [f64; 4]) -> f64 {
+        let mut sum = 0.0;
+        for (ai, row) in a.iter().zip(self.matrix.iter()) {
+            sum += ai * row.iter().zip(b.iter()).map(|(m, bj)| m * bj).sum::<f64>();
+        }
+        sum
+    }
+
+    // Ray parameter of the nearest intersection with `r` inside the bounds.
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+        let (t_min, t_max) = match self.bounds {
+            Some(bounds) => bounds.interval(r, t_min, t_max)?,
+            None => (t_min, t_max),
+        };
+        // Solving from near the bounds keeps the coefficients of distant rays from cancelling.
+        let shift = if t_min.is_finite() {
+            t_min.max(0.0)
+        } else {
+            0.0
+        };
+        let o = r.at(shift);
+        let d = r.direction();
+
+        // (o + t d)^T Q (o + t d), where the direction d has no homogeneous component.
+        let o = [o.x(), o.y(), o.z(), 1.0];
+        let d = [d.x(), d.y(), d.z(), 0.0];
+        let (a, b, c) = (self.form(d, d), 2.0 * self.form(d, o), self.form(o, o));
+        solve_quadratic(c, b, a)
+            .into_iter()
+            .map(|t| t + shift)
+            .find(|&t| {
+                t > t_min && t < t_max && self.bounds.is_none_or(|bounds| bounds.contains(r.at(t)))
+            })
+    }
+
+    // Outward normal at `p`, along the gradient 2 (Q p)_xyz.
+    fn normal(&self, p: Point3) -> Vec3 {
+        let mut gradient = Vec3::new(0.0, 0.0, 0.0);
+        for axis in 0..3 {
+            let row = &self.matrix[axis];
+            gradient[axis] = row[0] * p.x() + row[1] * p.y() + row[2] * p.z() + row[3];
+        }
+        unit_vector(gradient)
+    }
+}
+
+impl Shape for Quadric {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let t = match self.intersect(&r, t_min, t_max) {
+            Some(t) => t,
+            None => return false,
+        };
+        rec.t = t;
+        rec.p = r.at(t);
+        rec.set_face_normal(r, self.normal(rec.p));
+        rec.u = 0.0;
+        rec.v = 0.0;
+        rec.dpdu = Vec3::new(0.0, 0.0, 0.0);
+        rec.dpdv = Vec3::new(0.0, 0.0, 0.0);
+        true
+    }
+
+    fn occluded(&self, r: &Ray, t_max: f64) -> bool {
+        self.intersect(r, SHADOW_EPSILON, t_max).is_some()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds
+    }
+}
+
+fn diagonal(a: f64, b: f64, c: f64, d: f64) -> [[f64; 4]; 4] {
+    let mut matrix = [[0.0; 4]; 4];
+    for (i, value) in [a, b, c, d].into_iter().enumerate() {
+        matrix[i][i] = value;
+    }
+    matrix
+}
+
+// The matrix of the quadric `matrix` moved by `offset`: T^T Q T for the translation T taking
+// p to p - offset.
+fn translated(matrix: [[f64; 4]; 4], offset: Vec3) -> [[f64; 4]; 4] {
+    let mut t = diagonal(1.0, 1.0, 1.0, 1.0);
+    for axis in 0..3 {
+        t[axis][3] = -offset[axis];
+    }
+    let mut result = [[0.0; 4]; 4];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, entry) in row.iter_mut().enumerate() {
+            for k in 0..4 {
+                for l in 0..4 {
+                    *entry += t[k][i] * matrix[k][l] * t[l][j];
+                }
+            }
+        }
+    }
+    result
+}
@@ -4,6 +4,7 @@ use crate::vec3::{Point3, Vec3};
 pub struct Ray {
     orig: Point3,
     dir: Vec3,
+    wavelength: Option<f64>,
 }
 
 impl Ray {
@@ -11,6 +12,14 @@ impl Ray {
         Self {
             orig: origin,
             dir: direction,
+            wavelength: None,
+        }
+    }
+
+    pub fn with_wavelength(self, wavelength: f64) -> Self {
+        Self {
+            wavelength: Some(wavelength),
+            ..self
         }
     }
 
@@ -22,6 +31,11 @@ impl Ray {
         self.dir
     }
 
+    // Wavelength in nm carried by the ray in spectral mode; `None` when rendering in RGB.
+    pub fn wavelength(self) -> Option<f64> {
+        self.wavelength
+    }
+
     pub fn at(self, t: f64) -> Point3 {
         self.orig + t * self.dir
     }
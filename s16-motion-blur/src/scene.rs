@@ -0,0 +1,351 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    background::Background,
+    bump::LeakGuard,
+    bvh::Bvh,
+    hittable::{HitRecord, Hittable, Material, SHADOW_EPSILON},
+    light::{AreaLight, Light},
+    light_sampler::{LightSampler, LightSampling},
+    medium::HenyeyGreenstein,
+    medium_stack::{HitMedia, MediumStack},
+    ray::Ray,
+    util::random_f64,
+    vec3::{Color, Point3, Vec3},
+};
+
+pub struct Scene {
+    pub objects: Vec<Hittable>,
+    pub lights: Vec<Arc<dyn Light>>,
+    pub background: Box<dyn Background>,
+    area_lights: Vec<Option<usize>>,
+    // Phase functions of the objects with scattering interiors, shared by every collision in
+    // their volumes.
+    phase_functions: Vec<Option<Arc<dyn Material>>>,
+    bounds: Aabb,
+    // Objects with finite bounds are found through the hierarchy, the others tested one by one.
+    bvh: Bvh,
+    unbounded: Vec<usize>,
+    // Lights at infinity that escaping rays can see, as indices into `lights`.
+    infinite_lights: Vec<usize>,
+    light_sampling: LightSampling,
+    light_sampler: Box<dyn LightSampler>,
+    // Chooses lights to start paths from, where there is no shading point.
+    emission_sampler: Box<dyn LightSampler>,
+}
+
+impl Scene {
+    // Emissive objects are registered as area lights automatically, as is the light of the
+    // background, and the materials of objects with perturbed shading normals are guarded
+    // against light leaks.
+    pub fn new<B: 'static + Background>(mut objects: Vec<Hittable>, background: B) -> Self {
+        for h in objects.iter_mut() {
+            if h.shape.perturbs_normals() {
+                h.material = Arc::new(LeakGuard::new(Arc::clone(&h.material)));
+            }
+        }
+        let mut lights: Vec<Arc<dyn Light>> = Vec::new();
+        let mut area_lights = Vec::with_capacity(objects.len());
+        for h in objects.iter() {
+            if h.material.is_emissive() {
+                area_lights.push(Some(lights.len()));
+                lights.push(Arc::new(AreaLight::new(h.clone())));
+            } else {
+                area_lights.push(None);
+            }
+        }
+        let mut bounded = Vec::with_capacity(objects.len());
+        let mut unbounded = Vec::new();
+        for (i, h) in objects.iter().enumerate() {
+            match h.shape.bounding_box() {
+                Some(b) => bounded.push((i, b)),
+                None => unbounded.push(i),
+            }
+        }
+        let bvh = Bvh::build(bounded);
+        let phase_functions = objects
+            .iter()
+            .map(|h| match h.material.interior(None) {
+                Some(interior) if interior.scattering.length_squared() > 0.0 => {
+                    let white = Color::new(1.0, 1.0, 1.0);
+                    let phase: Arc<dyn Material> =
+                        Arc::new(HenyeyGreenstein::new(white, interior.g));
+                    Some(phase)
+                }
+                _ => None,
+            })
+            .collect();
+
+        let background_light = background.light();
+        let light_sampling = LightSampling::Bvh;
+        let mut scene = Self {
+            light_sampler: light_sampling.build(&lights),
+            emission_sampler: LightSampling::Power.build(&lights),
+            objects,
+            lights,
+            background: Box::new(background),
+            area_lights,
+            phase_functions,
+            bounds: bvh.bounds(),
+            bvh,
+            unbounded,
+            infinite_lights: Vec::new(),
+            light_sampling,
+        };
+        if let Some(light) = background_light {
+            scene.add_lights(vec![light]);
+        }
+        scene
+    }
+
+    // Bounds of all objects with finite extent.
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    pub fn set_light_sampling(&mut self, light_sampling: LightSampling) {
+        self.light_sampling = light_sampling;
+        self.rebuild_light_samplers();
+    }
+
+    fn rebuild_light_samplers(&mut self) {
+        self.infinite_lights = (0..self.lights.len())
+            .filter(|&i| self.lights[i].is_infinite() && !self.lights[i].is_delta())
+            .collect();
+        self.light_sampler = self.light_sampling.build(&self.lights);
+        self.emission_sampler = match self.light_sampling {
+            LightSampling::Uniform => LightSampling::Uniform.build(&self.lights),
+            _ => LightSampling::Power.build(&self.lights),
+        };
+    }
+
+    // Picks a light to sample from the point `p` with normal `n`, returning its index into
+    // `lights` and its probability.
+    pub fn sample_light(&self, p: Point3, n: Vec3) -> Option<(usize, f64)> {
+        self.light_sampler.sample(p, n, random_f64())
+    }
+
+    pub fn light_pmf(&self, p: Point3, n: Vec3, light: usize) -> f64 {
+        self.light_sampler.pmf(p, n, light)
+    }
+
+    // Picks a light to emit a path from.
+    pub fn sample_emitting_light(&self) -> Option<(usize, f64)> {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        self.emission_sampler.sample(zero, zero, random_f64())
+    }
+
+    pub fn emitting_light_pmf(&self, light: usize) -> f64 {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        self.emission_sampler.pmf(zero, zero, light)
+    }
+
+    // Lights at infinity that rays escaping the scene can reach, as indices into `lights`.
+    pub fn infinite_lights(&self) -> &[usize] {
+        &self.infinite_lights
+    }
+
+    // Radiance arriving along `r` once it escapes the scene, from the background and the
+    // lights at infinity, for integrators that do not sample lights.
+    pub fn escaped(&self, r: Ray) -> Color {
+        self.infinite_lights
+            .iter()
+            .fold(self.background.value(r), |l, &i| l + self.lights[i].le(&r))
+    }
+
+    // Index into `lights` of the area light created for `objects[object_index]`.
+    pub fn area_light(&self, object_index: usize) -> Option<usize> {
+        self.area_lights[object_index]
+    }
+
+    pub fn add_light<L: 'static + Light>(&mut self, light: L) {
+        self.add_lights(vec![Box::new(light)]);
+    }
+
+    // Like `add_light` for many lights, building the light samplers once for all of them.
+    pub fn add_lights(&mut self, lights: Vec<Box<dyn Light>>) {
+        for mut light in lights {
+            light.preprocess(&self.bounds);
+            self.lights.push(Arc::from(light));
+        }
+        self.rebuild_light_samplers();
+    }
+
+    pub fn hit(
+        &self,
+        r: Ray,
+        media: &MediumStack,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(HitRecord, HitMedia)> {
+        self.hit_object(r, media, t_min, t_max)
+            .map(|(_, rec, hit_media)| (rec, hit_media))
+    }
+
+    // Like `hit`, also returning the index of the object that was hit. `media` are the
+    // dielectrics the ray starts inside of, and the media on both sides of the surface are
+    // returned beside the record. Boundaries of dielectrics hidden inside a higher priority one
+    // are skipped, and the record carries the absorption along the way. Mixed materials are
+    // resolved to the one chosen for the hit. Inside a scattering interior the hit may be a
+    // collision in its volume instead, with a zero normal.
+    pub fn hit_object(
+        &self,
+        r: Ray,
+        media: &MediumStack,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(usize, HitRecord, HitMedia)> {
+        let mut media = *media;
+        let mut t_start = t_min;
+        let mut t_prev = 0.0;
+        let mut transmittance = Color::new(1.0, 1.0, 1.0);
+        let length = r.direction().length();
+
+        loop {
+            let (object, mut rec) = self.closest_hit(r, t_start, t_max)?;
+            while let Some(material) = rec.material.choose(&rec) {
+                rec.material = material;
+            }
+            let (incident, transmitted, is_true_hit) = match rec.material.interior(r.wavelength()) {
+                Some(interior) => {
+                    // A ray leaving an object it was never seen entering started inside it.
+                    let incident = if rec.front_face {
+                        media
+                    } else {
+                        media.with(object, interior)
+                    };
+                    let transmitted = if rec.front_face {
+                        media.with(object, interior)
+                    } else {
+                        media.without(object)
+                    };
+                    // Only the owner of the volume on either side has a visible boundary.
+                    let owner = if rec.front_face {
+                        transmitted
+                    } else {
+                        incident
+                    };
+                    let is_true_hit = owner.top().is_none_or(|(o, _)| o == object);
+                    (incident, transmitted, is_true_hit)
+                }
+                None => (media, media, true),
+            };
+
+            // Scattering interiors can stop the ray before it reaches the surface.
+            let (collision, weight) = incident.sample_scattering((rec.t - t_prev) * length);
+            transmittance = transmittance * weight;
+            if let (Some(distance), Some((owner, interior))) = (collision, incident.top()) {
+                // Interiors of mixed materials are only known once one has been chosen.
+                let phase = match &self.phase_functions[owner] {
+                    Some(phase) => Arc::clone(phase),
+                    None => Arc::new(HenyeyGreenstein::new(Color::new(1.0, 1.0, 1.0), interior.g)),
+                };
+                let mut rec = HitRecord::new(phase);
+                rec.t = t_prev + distance / length;
+                rec.p = r.at(rec.t);
+                rec.front_face = true;
+                rec.ior = incident.ior();
+                rec.transmitted_ior = incident.ior();
+                rec.transmittance = transmittance;
+                rec.wavelength = r.wavelength();
+                let hit_media = HitMedia {
+                    incident,
+                    transmitted: incident,
+                };
+                return Some((owner, rec, hit_media));
+            }
+            if is_true_hit {
+                rec.ior = incident.ior();
+                rec.transmitted_ior = transmitted.ior();
+                rec.transmittance = transmittance;
+                rec.wavelength = r.wavelength();
+                let hit_media = HitMedia {
+                    incident,
+                    transmitted,
+                };
+                return Some((object, rec, hit_media));
+            }
+            media = transmitted;
+            t_prev = rec.t;
+            t_start = rec.t + 0.001;
+        }
+    }
+
+    // Shapes fill one scratch record, which is swapped with the nearest so far on every closer
+    // hit, and only the winner gets its material.
+    fn closest_hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<(usize, HitRecord)> {
+        let mut closest_so_far = t_max;
+        let mut scratch: Option<HitRecord> = None;
+        let mut closest: Option<(usize, HitRecord)> = None;
+
+        let mut test = |i: usize, t_max: &mut f64| {
+            let hittable = &self.objects[i];
+            let rec = scratch.get_or_insert_with(|| HitRecord::new(Arc::clone(&hittable.material)));
+            rec.reset();
+            if hittable.shape.hit(r, t_min, *t_max, rec) {
+                *t_max = rec.t;
+                let rec = scratch.take().unwrap();
+                scratch = closest.take().map(|(_, rec)| rec);
+                closest = Some((i, rec));
+            }
+            true
+        };
+        for &i in self.unbounded.iter() {
+            test(i, &mut closest_so_far);
+        }
+        self.bvh.traverse(&r, t_min, closest_so_far, &mut test);
+
+        closest.map(|(i, mut rec)| {
+            rec.material = Arc::clone(&self.objects[i].material);
+            (i, rec)
+        })
+    }
+
+    pub fn occluded(&self, r: &Ray, t_max: f64) -> bool {
+        if self
+            .unbounded
+            .iter()
+            .any(|&i| self.objects[i].shape.occluded(r, t_max))
+        {
+            return true;
+        }
+        let mut occluded = false;
+        self.bvh.traverse(r, SHADOW_EPSILON, t_max, |i, _| {
+            occluded = self.objects[i].shape.occluded(r, t_max);
+            !occluded
+        });
+        occluded
+    }
+
+    // Whether the segment of length `distance` from `p` along the unit `direction` is blocked,
+    // stopping just short of its end point.
+    pub fn segment_occluded(&self, p: Point3, direction: Vec3, distance: f64) -> bool {
+        self.occluded(&Ray::new(p, direction), distance * (1.0 - 1e-4))
+    }
+
+    pub fn transmittance(&self, r: &Ray, t_max: f64) -> f64 {
+        let mut transmittance = 1.0;
+        for &i in self.unbounded.iter() {
+            transmittance *= self.objects[i].shape.transmittance(r, t_max);
+            if transmittance == 0.0 {
+                return 0.0;
+            }
+        }
+        self.bvh.traverse(r, SHADOW_EPSILON, t_max, |i, _| {
+            transmittance *= self.objects[i].shape.transmittance(r, t_max);
+            transmittance > 0.0
+        });
+        transmittance
+    }
+
+    // Like `segment_occluded`, for shadow rays that may pass through media.
+    pub fn segment_transmittance(&self, p: Point3, direction: Vec3, distance: f64) -> f64 {
+        self.transmittance(&Ray::new(p, direction), distance * (1.0 - 1e-4))
+    }
+
+    // Density of choosing `light` from `p` with normal `n` and then sampling `wi` from it.
+    pub fn light_pdf(&self, p: Point3, n: Vec3, light: usize, wi: Vec3) -> f64 {
+        self.light_pmf(p, n, light) * self.lights[light].pdf_li(p, wi)
+    }
+}
@@ -0,0 +1,179 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    hittable::{HitRecord, Material},
+    material::Lambertian,
+    microfacet::reflect_local,
+    onb::Onb,
+    ray::Ray,
+    texture::Texture,
+    util::random_f64,
+    vec3::{dot, unit_vector, Color, Vec3},
+};
+
+// Directional albedos tabulated for layering, at evenly spaced cosines.
+const ALBEDO_SAMPLES: usize = 32;
+
+// Cloth and velvet: the "Charlie" sheen distribution with Estevez and Kulla's shadowing
+// ("Production Friendly Microfacet Sheen BRDF", 2017), which reflects mostly at grazing
+// angles. On its own it is the fuzz of a dark fabric; over a Lambertian base the base
+// receives the light the sheen does not reflect.
+//
+// `scatter` samples half vectors from the distribution or cosine-weighted directions, half
+// the time each, so that both the sheen and the base are sampled well.
+pub struct Sheen {
+    color: Arc<dyn Texture>,
+    alpha: f64,
+    base: Option<Color>,
+    // Directional albedo of a white sheen.
+    albedo: [f64; ALBEDO_SAMPLES],
+}
+
+impl Sheen {
+    pub fn new<T: 'static + Texture>(color: T, roughness: f64) -> Self {
+        let mut sheen = Self {
+            color: Arc::new(color),
+            alpha: roughness.clamp(0.07, 1.0),
+            base: None,
+            albedo: [0.0; ALBEDO_SAMPLES],
+        };
+        for (i, albedo) in sheen.albedo.iter_mut().enumerate() {
+            let cos_o = (i as f64 / (ALBEDO_SAMPLES - 1) as f64).max(1e-3);
+            *albedo = directional_albedo(sheen.alpha, cos_o);
+        }
+        sheen
+    }
+
+    pub fn with_base(mut self, base: Lambertian) -> Self {
+        self.base = Some(base.albedo);
+        self
+    }
+
+    fn albedo(&self, cos_o: f64) -> f64 {
+        let x = cos_o.clamp(0.0, 1.0) * (ALBEDO_SAMPLES - 1) as f64;
+        let i = (x as usize).min(ALBEDO_SAMPLES - 2);
+        let t = x - i as f64;
+        (1.0 - t) * self.albedo[i] + t * self.albedo[i + 1]
+    }
+
+    // BSDF for local directions above the surface.
+    fn f(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let color = self.color.value(rec.u, rec.v, rec.p);
+        let mut f = sheen(self.alpha, wo, wi) * color;
+        if let Some(base) = self.base {
+            let covered = color.x().max(color.y()).max(color.z()) * self.albedo(wo.z());
+            f += base * ((1.0 - covered).max(0.0) / PI);
+        }
+        f
+    }
+
+    fn pdf_local(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let wm = unit_vector(wo + wi);
+        let half = charlie(self.alpha, wm.z()) * wm.z() / (4.0 * dot(wo, wm));
+        0.5 * half + 0.5 * wi.z() / PI
+    }
+}
+
+impl Material for Sheen {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+    ) -> bool {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-unit_vector(r_in.direction()));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let wi = if random_f64() < 0.5 {
+            // The distribution times cos(theta_m) has the CDF sin(theta_m)^(2 + 1 / alpha).
+            let sin_m = random_f64().powf(1.0 / (2.0 + 1.0 / self.alpha));
+            let cos_m = (1.0 - sin_m * sin_m).max(0.0).sqrt();
+            let phi = 2.0 * PI * random_f64();
+            reflect_local(wo, Vec3::new(sin_m * phi.cos(), sin_m * phi.sin(), cos_m))
+        } else {
+            Vec3::random_cosine_direction()
+        };
+        let pdf = self.pdf_local(wo, wi);
+        if pdf <= 0.0 {
+            return false;
+        }
+
+        *attenuation = self.f(rec, wo, wi) * (wi.z() / pdf);
+        *scattered = Ray::new(rec.p, uvw.local(wi));
+        true
+    }
+
+    fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let uvw = Onb::build_from_w(rec.normal);
+        let (wo, wi) = (uvw.to_local(wo), uvw.to_local(wi));
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.f(rec, wo, wi) * wi.z()
+    }
+
+    fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let uvw = Onb::build_from_w(rec.normal);
+        self.pdf_local(uvw.to_local(wo), uvw.to_local(wi))
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+}
+
+// The "Charlie" distribution of fibre normals, for the cosine of the half vector.
+fn charlie(alpha: f64, cos_m: f64) -> f64 {
+    let sin2 = (1.0 - cos_m * cos_m).max(0.0);
+    (2.0 + 1.0 / alpha) * sin2.powf(0.5 / alpha) / (2.0 * PI)
+}
+
+// Estevez and Kulla's fit of the shadowing term.
+fn lambda(alpha: f64, cos_theta: f64) -> f64 {
+    let t = (1.0 - alpha) * (1.0 - alpha);
+    let lerp = |at_0: f64, at_1: f64| t * at_0 + (1.0 - t) * at_1;
+    let (a, b, c) = (
+        lerp(25.3245, 21.5473),
+        lerp(3.32435, 3.82987),
+        lerp(0.16801, 0.19823),
+    );
+    let (d, e) = (lerp(-1.27393, -1.97760), lerp(-4.85967, -4.32054));
+    let l = |x: f64| a / (1.0 + b * x.powf(c)) + d * x + e;
+    if cos_theta < 0.5 {
+        l(cos_theta).exp()
+    } else {
+        (2.0 * l(0.5) - l(1.0 - cos_theta)).exp()
+    }
+}
+
+// Sheen BRDF of a white fabric, for local directions above the surface.
+fn sheen(alpha: f64, wo: Vec3, wi: Vec3) -> f64 {
+    let wm = unit_vector(wo + wi);
+    let g = 1.0 / (1.0 + lambda(alpha, wo.z()) + lambda(alpha, wi.z()));
+    charlie(alpha, wm.z()) * g / (4.0 * wo.z() * wi.z())
+}
+
+// Integral of the white sheen times cos(theta_i) over the hemisphere, by the midpoint rule.
+fn directional_albedo(alpha: f64, cos_o: f64) -> f64 {
+    const N: usize = 64;
+    let wo = Vec3::new((1.0 - cos_o * cos_o).sqrt(), 0.0, cos_o);
+    let mut sum = 0.0;
+    for i in 0..N {
+        let cos_i = (i as f64 + 0.5) / N as f64;
+        let sin_i = (1.0 - cos_i * cos_i).sqrt();
+        for j in 0..N {
+            let phi = PI * (j as f64 + 0.5) / N as f64;
+            let wi = Vec3::new(sin_i * phi.cos(), sin_i * phi.sin(), cos_i);
+            sum += sheen(alpha, wo, wi) * cos_i;
+        }
+    }
+    // Symmetric in phi, so half the circle counts twice.
+    sum * (1.0 / N as f64) * (2.0 * PI / N as f64)
+}
@@ -0,0 +1,349 @@
+use std::f64::consts::PI;
+
+use crate::{
+    aabb::Aabb,
+    background::Background,
+    color::xyz_to_linear_srgb,
+    light::{EmissionSample, Light, LightSample},
+    onb::Onb,
+    ray::Ray,
+    util::random_f64,
+    vec3::{dot, random_in_unit_disk, unit_vector, Color, Point3, Vec3},
+};
+
+// Mean angular radius of the sun seen from the earth, in radians.
+pub const SUN_ANGULAR_RADIUS: f64 = 0.00465;
+// Extraterrestrial luminance of the solar disk, in kcd/m^2 like the Preetham zenith luminance.
+const SUN_LUMINANCE: f64 = 1.6e6;
+
+const ZENITH_X: [[f64; 4]; 3] = [
+    [0.00166, -0.00375, 0.00209, 0.0],
+    [-0.02903, 0.06377, -0.03202, 0.00394],
+    [0.11693, -0.21196, 0.06052, 0.25886],
+];
+const ZENITH_Y: [[f64; 4]; 3] = [
+    [0.00275, -0.00610, 0.00317, 0.0],
+    [-0.04214, 0.08970, -0.04153, 0.00516],
+    [0.15346, -0.26756, 0.06670, 0.26688],
+];
+
+/// Preetham et al. "A Practical Analytic Model for Daylight" sky dome with a sun disk.
+/// The zenith is +y, matching the `vup` used by the camera. The disk is left out of the
+/// background radiance and added to scenes as a `Sun` light, which samples it.
+#[derive(Clone)]
+pub struct PhysicalSky {
+    sun_direction: Vec3,
+    turbidity: f64,
+    ground_albedo: Color,
+    exposure: f64,
+    sun_cos_max: f64,
+    perez_lum: [f64; 5],
+    perez_x: [f64; 5],
+    perez_y: [f64; 5],
+    zenith: Vec3,
+    sun_radiance: Color,
+    ground_radiance: Color,
+}
+
+impl PhysicalSky {
+    pub fn new(sun_direction: Vec3, turbidity: f64, ground_albedo: Color) -> Self {
+        Self::with_exposure(sun_direction, turbidity, ground_albedo, 0.05)
+    }
+
+    pub fn with_exposure(
+        sun_direction: Vec3,
+        turbidity: f64,
+        ground_albedo: Color,
+        exposure: f64,
+    ) -> Self {
+        let sun_direction = unit_vector(sun_direction);
+        let t = turbidity.max(1.0);
+        let theta_s = sun_direction.y().clamp(-1.0, 1.0).acos().min(PI / 2.0);
+
+        let perez_lum = [
+            0.1787 * t - 1.4630,
+            -0.3554 * t + 0.4275,
+            -0.0227 * t + 5.3251,
+            0.1206 * t - 2.5771,
+            -0.0670 * t + 0.3703,
+        ];
+        let perez_x = [
+            -0.0193 * t - 0.2592,
+            -0.0665 * t + 0.0008,
+            -0.0004 * t + 0.2125,
+            -0.0641 * t - 0.8989,
+            -0.0033 * t + 0.0452,
+        ];
+        let perez_y = [
+            -0.0167 * t - 0.2608,
+            -0.0950 * t + 0.0092,
+            -0.0079 * t + 0.2102,
+            -0.0441 * t - 1.6537,
+            -0.0109 * t + 0.0529,
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_lum = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let zenith = Vec3::new(
+            zenith_lum,
+            zenith_chromaticity(&ZENITH_X, t, theta_s),
+            zenith_chromaticity(&ZENITH_Y, t, theta_s),
+        );
+
+        let mut sky = Self {
+            sun_direction,
+            turbidity: t,
+            ground_albedo,
+            exposure,
+            sun_cos_max: SUN_ANGULAR_RADIUS.cos(),
+            perez_lum,
+            perez_x,
+            perez_y,
+            zenith,
+            sun_radiance: Color::new(0.0, 0.0, 0.0),
+            ground_radiance: Color::new(0.0, 0.0, 0.0),
+        };
+        sky.sun_radiance = sky.compute_sun_radiance();
+        sky.ground_radiance = sky.compute_ground_radiance();
+        sky
+    }
+
+    pub fn sun_direction(&self) -> Vec3 {
+        self.sun_direction
+    }
+
+    pub fn turbidity(&self) -> f64 {
+        self.turbidity
+    }
+
+    pub fn sun_radiance(&self) -> Color {
+        self.sun_radiance
+    }
+
+    /// Radiance of the sky dome alone, without the sun disk.
+    pub fn sky_radiance(&self, direction: Vec3) -> Color {
+        let d = unit_vector(direction);
+        if d.y() <= 0.0 {
+            return self.ground_radiance;
+        }
+        let cos_theta = d.y().max(1e-3);
+        let gamma = dot(d, self.sun_direction).clamp(-1.0, 1.0).acos();
+        let theta_s = self.sun_direction.y().clamp(-1.0, 1.0).acos().min(PI / 2.0);
+
+        let lum = self.zenith.x() * perez(&self.perez_lum, cos_theta, gamma)
+            / perez(&self.perez_lum, 1.0, theta_s);
+        let x = self.zenith.y() * perez(&self.perez_x, cos_theta, gamma)
+            / perez(&self.perez_x, 1.0, theta_s);
+        let y = self.zenith.z() * perez(&self.perez_y, cos_theta, gamma)
+            / perez(&self.perez_y, 1.0, theta_s);
+
+        self.exposure * xyy_to_rgb(x, y, lum)
+    }
+
+    pub fn is_sun_direction(&self, direction: Vec3) -> bool {
+        dot(unit_vector(direction), self.sun_direction) >= self.sun_cos_max
+    }
+
+    /// Uniformly samples a direction inside the cone subtended by the sun disk.
+    pub fn sample_sun(&self) -> Vec3 {
+        let cos_theta = 1.0 - random_f64() * (1.0 - self.sun_cos_max);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = 2.0 * PI * random_f64();
+        Onb::build_from_w(self.sun_direction).local(Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+
+    pub fn sun_pdf(&self, direction: Vec3) -> f64 {
+        if self.is_sun_direction(direction) {
+            1.0 / self.sun_solid_angle()
+        } else {
+            0.0
+        }
+    }
+
+    pub fn sun_solid_angle(&self) -> f64 {
+        2.0 * PI * (1.0 - self.sun_cos_max)
+    }
+
+    fn compute_sun_radiance(&self) -> Color {
+        let cos_theta_s = self.sun_direction.y();
+        if cos_theta_s <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let theta_deg = cos_theta_s.acos().to_degrees();
+        let air_mass = 1.0 / (cos_theta_s + 0.15 * (93.885 - theta_deg).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+
+        // Rayleigh and Angstrom aerosol transmittance at representative RGB wavelengths (um).
+        let transmittance = |lambda: f64| {
+            let rayleigh = (-0.008735 * lambda.powf(-4.08) * air_mass).exp();
+            let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+            rayleigh * aerosol
+        };
+        let sun_white = xyy_to_rgb(0.3127, 0.3290, SUN_LUMINANCE);
+        self.exposure
+            * Color::new(
+                sun_white.x() * transmittance(0.680),
+                sun_white.y() * transmittance(0.550),
+                sun_white.z() * transmittance(0.440),
+            )
+    }
+
+    // Lambertian ground lit by the cosine-weighted sky dome and the sun.
+    fn compute_ground_radiance(&self) -> Color {
+        const N_THETA: usize = 32;
+        const N_PHI: usize = 64;
+        let d_theta = 0.5 * PI / N_THETA as f64;
+        let d_phi = 2.0 * PI / N_PHI as f64;
+
+        let mut irradiance = Color::new(0.0, 0.0, 0.0);
+        for i in 0..N_THETA {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..N_PHI {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let d = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                irradiance += self.sky_radiance(d) * (theta.cos() * theta.sin() * d_theta * d_phi);
+            }
+        }
+        irradiance +=
+            self.sun_radiance * (self.sun_solid_angle() * self.sun_direction.y().max(0.0));
+
+        self.ground_albedo * irradiance / PI
+    }
+}
+
+impl Background for PhysicalSky {
+    fn value(&self, r: Ray) -> Color {
+        self.sky_radiance(r.direction())
+    }
+
+    fn light(&self) -> Option<Box<dyn Light>> {
+        Some(Box::new(Sun::new(self.clone())))
+    }
+}
+
+/// The sun disk of a `PhysicalSky` as a light at infinity. Its solid angle is far too small
+/// for scattered rays to find it reliably, so it is sampled uniformly over its cone. Emitting
+/// rays from it needs a sphere bounding the scene, taken from the scene when it is added.
+pub struct Sun {
+    sky: PhysicalSky,
+    scene_bounds: Option<(Point3, f64)>,
+}
+
+impl Sun {
+    pub fn new(sky: PhysicalSky) -> Self {
+        Self {
+            sky,
+            scene_bounds: None,
+        }
+    }
+
+    fn is_visible(&self) -> bool {
+        self.sky.sun_direction().y() > 0.0
+    }
+}
+
+impl Light for Sun {
+    fn sample_li(&self, _p: Point3) -> Option<LightSample> {
+        if !self.is_visible() {
+            return None;
+        }
+        Some(LightSample {
+            wi: self.sky.sample_sun(),
+            distance: f64::INFINITY,
+            radiance: self.sky.sun_radiance(),
+            pdf: 1.0 / self.sky.sun_solid_angle(),
+        })
+    }
+
+    fn pdf_li(&self, _p: Point3, wi: Vec3) -> f64 {
+        if !self.is_visible() {
+            return 0.0;
+        }
+        self.sky.sun_pdf(wi)
+    }
+
+    fn le(&self, r: &Ray) -> Color {
+        if !self.is_visible() || !self.sky.is_sun_direction(r.direction()) {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.sky.sun_radiance()
+    }
+
+    // Rays start on a disk facing the sun that covers the bounding sphere, and leave in a
+    // direction sampled from the disk's cone.
+    fn sample_le(&self) -> Option<EmissionSample> {
+        let (center, radius) = self.scene_bounds?;
+        if !self.is_visible() {
+            return None;
+        }
+        let frame = Onb::build_from_w(self.sky.sun_direction());
+        let disk = random_in_unit_disk() * radius;
+        let origin = center + self.sky.sun_direction() * radius + frame.local(disk);
+
+        Some(EmissionSample {
+            ray: Ray::new(origin, -self.sky.sample_sun()),
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            radiance: self.sky.sun_radiance(),
+            pdf_pos: 1.0 / (PI * radius * radius),
+            pdf_dir: 1.0 / self.sky.sun_solid_angle(),
+        })
+    }
+
+    fn pdf_le(&self, _p: Point3, _n: Vec3, w: Vec3) -> (f64, f64) {
+        match self.scene_bounds {
+            Some((_, radius)) => (1.0 / (PI * radius * radius), self.sky.sun_pdf(-w)),
+            None => (0.0, 0.0),
+        }
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+
+    // The power crossing the bounding sphere, with the disk's irradiance at normal incidence.
+    fn power(&self) -> f64 {
+        match self.scene_bounds {
+            Some((_, radius)) if self.is_visible() => {
+                let irradiance = self.sky.sun_radiance() * self.sky.sun_solid_angle();
+                PI * radius * radius * (irradiance.x() + irradiance.y() + irradiance.z()) / 3.0
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn preprocess(&mut self, scene_bounds: &Aabb) {
+        if self.scene_bounds.is_none() && !scene_bounds.is_empty() {
+            self.scene_bounds = Some(scene_bounds.bounding_sphere());
+        }
+    }
+}
+
+fn perez(coeffs: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coeffs;
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+fn zenith_chromaticity(m: &[[f64; 4]; 3], t: f64, theta_s: f64) -> f64 {
+    let tv = [t * t, t, 1.0];
+    let sv = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+    (0..3)
+        .map(|i| tv[i] * (0..4).map(|j| m[i][j] * sv[j]).sum::<f64>())
+        .sum()
+}
+
+fn xyy_to_rgb(x: f64, y: f64, lum: f64) -> Color {
+    if y <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    let xyz = Vec3::new(x / y * lum, lum, (1.0 - x - y) / y * lum);
+    let rgb = xyz_to_linear_srgb(xyz);
+    Color::new(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+}
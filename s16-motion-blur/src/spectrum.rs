@@ -0,0 +1,268 @@
+use std::ops::{Add, AddAssign, Index, IndexMut, Mul, MulAssign};
+use std::sync::OnceLock;
+
+use crate::{
+    color::xyz_to_linear_srgb,
+    vec3::{Color, Vec3},
+};
+
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 720.0;
+pub const N_SPECTRUM_SAMPLES: usize = 4;
+
+const SMITS_BINS: usize = 10;
+
+// Smits 1999, "An RGB-to-Spectrum Conversion for Reflectances", 10 bins over [380, 720] nm.
+const SMITS_WHITE: [f64; SMITS_BINS] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; SMITS_BINS] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; SMITS_BINS] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; SMITS_BINS] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; SMITS_BINS] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; SMITS_BINS] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; SMITS_BINS] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct SampledSpectrum {
+    values: [f64; N_SPECTRUM_SAMPLES],
+}
+
+impl SampledSpectrum {
+    pub fn splat(v: f64) -> Self {
+        Self {
+            values: [v; N_SPECTRUM_SAMPLES],
+        }
+    }
+
+    // Upsamples a linear RGB triple (reflectance or radiance) at the given wavelengths.
+    pub fn from_rgb(rgb: Color, lambdas: &SampledWavelengths) -> Self {
+        let mut s = Self::splat(0.0);
+        for i in 0..N_SPECTRUM_SAMPLES {
+            s.values[i] = rgb_to_spectrum(rgb, lambdas.lambda[i]);
+        }
+        s
+    }
+
+    pub fn is_black(&self) -> bool {
+        self.values.iter().all(|&v| v == 0.0)
+    }
+
+    pub fn max_value(&self) -> f64 {
+        self.values.iter().cloned().fold(0.0, f64::max)
+    }
+}
+
+impl Index<usize> for SampledSpectrum {
+    type Output = f64;
+    fn index(&self, i: usize) -> &Self::Output {
+        &self.values[i]
+    }
+}
+
+impl IndexMut<usize> for SampledSpectrum {
+    fn index_mut(&mut self, i: usize) -> &mut Self::Output {
+        &mut self.values[i]
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = SampledSpectrum;
+    fn add(self, s: SampledSpectrum) -> SampledSpectrum {
+        let mut out = self;
+        out += s;
+        out
+    }
+}
+
+impl AddAssign for SampledSpectrum {
+    fn add_assign(&mut self, s: SampledSpectrum) {
+        for i in 0..N_SPECTRUM_SAMPLES {
+            self.values[i] += s.values[i];
+        }
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = SampledSpectrum;
+    fn mul(self, s: SampledSpectrum) -> SampledSpectrum {
+        let mut out = self;
+        out *= s;
+        out
+    }
+}
+
+impl MulAssign for SampledSpectrum {
+    fn mul_assign(&mut self, s: SampledSpectrum) {
+        for i in 0..N_SPECTRUM_SAMPLES {
+            self.values[i] *= s.values[i];
+        }
+    }
+}
+
+impl Mul<f64> for SampledSpectrum {
+    type Output = SampledSpectrum;
+    fn mul(self, t: f64) -> SampledSpectrum {
+        let mut out = self;
+        for v in out.values.iter_mut() {
+            *v *= t;
+        }
+        out
+    }
+}
+
+// Hero wavelength sampling (Wilkie et al. 2014): one uniformly sampled wavelength and
+// the others rotated by equal offsets through the visible range.
+#[derive(Debug, Clone, Copy)]
+pub struct SampledWavelengths {
+    lambda: [f64; N_SPECTRUM_SAMPLES],
+    pdf: [f64; N_SPECTRUM_SAMPLES],
+}
+
+impl SampledWavelengths {
+    pub fn sample_uniform(u: f64) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let delta = range / N_SPECTRUM_SAMPLES as f64;
+        let hero = LAMBDA_MIN + u * range;
+
+        let mut lambda = [0.0; N_SPECTRUM_SAMPLES];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            let mut v = hero + i as f64 * delta;
+            if v > LAMBDA_MAX {
+                v -= range;
+            }
+            *l = v;
+        }
+        Self {
+            lambda,
+            pdf: [1.0 / range; N_SPECTRUM_SAMPLES],
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn lambda(&self, i: usize) -> f64 {
+        self.lambda[i]
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|&p| p == 0.0)
+    }
+
+    // Wavelength-dependent scattering such as dispersion can only follow the hero wavelength.
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        for p in self.pdf[1..].iter_mut() {
+            *p = 0.0;
+        }
+        self.pdf[0] /= N_SPECTRUM_SAMPLES as f64;
+    }
+
+    // Monte Carlo estimate of the CIE XYZ tristimulus of `l`, converted to white-balanced
+    // linear sRGB.
+    pub fn to_rgb(&self, l: SampledSpectrum) -> Color {
+        let mut xyz = Vec3::new(0.0, 0.0, 0.0);
+        for i in 0..N_SPECTRUM_SAMPLES {
+            if self.pdf[i] == 0.0 {
+                continue;
+            }
+            xyz += (l[i] / self.pdf[i]) * cie_xyz(self.lambda[i]);
+        }
+        xyz /= N_SPECTRUM_SAMPLES as f64 * cie_y_integral();
+
+        let white = flat_spectrum_rgb();
+        let rgb = xyz_to_linear_srgb(xyz);
+        Color::new(
+            rgb.x() / white.x(),
+            rgb.y() / white.y(),
+            rgb.z() / white.z(),
+        )
+    }
+}
+
+// Multi-lobe Gaussian fit of the CIE 1931 matching functions (Wyman, Sloan and Shirley 2013).
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let g = |mu: f64, s1: f64, s2: f64| {
+        let t = (lambda - mu) / if lambda < mu { s1 } else { s2 };
+        (-0.5 * t * t).exp()
+    };
+    Vec3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+fn cie_y_integral() -> f64 {
+    static INTEGRAL: OnceLock<f64> = OnceLock::new();
+    *INTEGRAL.get_or_init(|| integrate_cie(|_| 1.0).y())
+}
+
+// Linear sRGB of a spectrally flat unit radiance, used as the film white point.
+fn flat_spectrum_rgb() -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    *WHITE.get_or_init(|| {
+        let xyz = integrate_cie(|_| 1.0);
+        xyz_to_linear_srgb(xyz / xyz.y())
+    })
+}
+
+fn integrate_cie(spectrum: impl Fn(f64) -> f64) -> Vec3 {
+    let mut xyz = Vec3::new(0.0, 0.0, 0.0);
+    let mut lambda = LAMBDA_MIN;
+    while lambda <= LAMBDA_MAX {
+        xyz += spectrum(lambda) * cie_xyz(lambda);
+        lambda += 1.0;
+    }
+    xyz
+}
+
+fn smits_lookup(table: &[f64; SMITS_BINS], lambda: f64) -> f64 {
+    let x = (lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN) * SMITS_BINS as f64;
+    let bin = (x.max(0.0) as usize).min(SMITS_BINS - 1);
+    table[bin]
+}
+
+pub fn rgb_to_spectrum(rgb: Color, lambda: f64) -> f64 {
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+    let s = |table: &[f64; SMITS_BINS]| smits_lookup(table, lambda);
+
+    if r <= g && r <= b {
+        r * s(&SMITS_WHITE)
+            + if g <= b {
+                (g - r) * s(&SMITS_CYAN) + (b - g) * s(&SMITS_BLUE)
+            } else {
+                (b - r) * s(&SMITS_CYAN) + (g - b) * s(&SMITS_GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * s(&SMITS_WHITE)
+            + if r <= b {
+                (r - g) * s(&SMITS_MAGENTA) + (b - r) * s(&SMITS_BLUE)
+            } else {
+                (b - g) * s(&SMITS_MAGENTA) + (r - b) * s(&SMITS_RED)
+            }
+    } else {
+        b * s(&SMITS_WHITE)
+            + if r <= g {
+                (r - b) * s(&SMITS_YELLOW) + (g - r) * s(&SMITS_GREEN)
+            } else {
+                (g - b) * s(&SMITS_YELLOW) + (r - g) * s(&SMITS_RED)
+            }
+    }
+}
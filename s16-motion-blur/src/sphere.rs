@@ -1,7 +1,12 @@
+use std::f64::consts::PI;
+
 use crate::{
-    hittable::{HitRecord, Shape},
+    aabb::Aabb,
+    hittable::{HitRecord, Shape, SHADOW_EPSILON},
+    onb::Onb,
     ray::Ray,
-    vec3::{dot, Point3},
+    util::random_f64,
+    vec3::{dot, Point3, Vec3},
 };
 
 pub struct Sphere {
@@ -13,6 +18,25 @@ impl Sphere {
     pub fn new(center: Point3, radius: f64) -> Self {
         Self { center, radius }
     }
+
+    // Derivatives of the point with outward normal `n` along the `sphere_uv` coordinates.
+    // At the poles, where they degenerate, any tangent frame is used.
+    fn tangents(&self, n: Vec3) -> (Vec3, Vec3) {
+        let sin_theta = (1.0 - n.y() * n.y()).max(0.0).sqrt();
+        if sin_theta < 1e-8 {
+            let uvw = Onb::build_from_w(n);
+            return (self.radius * uvw.u, self.radius * uvw.v);
+        }
+        let dpdu = 2.0 * PI * self.radius * Vec3::new(n.z(), 0.0, -n.x());
+        let dpdv = PI
+            * self.radius
+            * Vec3::new(
+                -n.x() * n.y() / sin_theta,
+                sin_theta,
+                -n.y() * n.z() / sin_theta,
+            );
+        (dpdu, dpdv)
+    }
 }
 
 impl Shape for Sphere {
@@ -31,6 +55,8 @@ impl Shape for Sphere {
                 rec.p = r.at(rec.t);
                 let outward_normal = (rec.p - self.center) / self.radius;
                 rec.set_face_normal(r, outward_normal);
+                (rec.u, rec.v) = sphere_uv(outward_normal);
+                (rec.dpdu, rec.dpdv) = self.tangents(outward_normal);
                 return true;
             }
             let temp = (-half_b + root) / a;
@@ -39,9 +65,76 @@ impl Shape for Sphere {
                 rec.p = r.at(rec.t);
                 let outward_normal = (rec.p - self.center) / self.radius;
                 rec.set_face_normal(r, outward_normal);
+                (rec.u, rec.v) = sphere_uv(outward_normal);
+                (rec.dpdu, rec.dpdv) = self.tangents(outward_normal);
                 return true;
             }
         }
         false
     }
+
+    fn occluded(&self, r: &Ray, t_max: f64) -> bool {
+        let oc = r.origin() - self.center;
+        let a = r.direction().length_squared();
+        let half_b = dot(oc, r.direction());
+        let c = oc.length_squared() - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant <= 0.0 {
+            return false;
+        }
+
+        let root = discriminant.sqrt();
+        let near = (-half_b - root) / a;
+        let far = (-half_b + root) / a;
+        (near > SHADOW_EPSILON && near < t_max) || (far > SHADOW_EPSILON && far < t_max)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        if !self.occluded(&Ray::new(origin, direction), f64::INFINITY) {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center - origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return 1.0 / (4.0 * PI);
+        }
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    // Samples the cone of directions subtended by the sphere, or all directions from inside.
+    fn random(&self, origin: Point3) -> Vec3 {
+        let direction = self.center - origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return Vec3::random_unit_vector();
+        }
+
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let z = 1.0 + random_f64() * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * random_f64();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        Onb::build_from_w(direction).local(Vec3::new(r * phi.cos(), r * phi.sin(), z))
+    }
+
+    fn area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    fn sample_area(&self) -> Option<(Point3, Vec3)> {
+        let n = Vec3::random_unit_vector();
+        Some((self.center + self.radius * n, n))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+}
+
+// u is the angle around the y axis starting at -x, v the angle from -y to +y, both in [0, 1].
+fn sphere_uv(p: Vec3) -> (f64, f64) {
+    let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z()).atan2(p.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
 }
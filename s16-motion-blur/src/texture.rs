@@ -0,0 +1,220 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use crate::{
+    perlin::Perlin,
+    vec3::{Color, Point3},
+};
+
+// Spatially varying material parameters. Scalar parameters read the first channel.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color;
+}
+
+impl Texture for Color {
+    fn value(&self, _u: f64, _v: f64, _p: Point3) -> Color {
+        *self
+    }
+}
+
+impl Texture for f64 {
+    fn value(&self, _u: f64, _v: f64, _p: Point3) -> Color {
+        Color::new(*self, *self, *self)
+    }
+}
+
+// Alternates between two textures in a 3D checkerboard with cells of size `scale`.
+pub struct Checker<A: Texture, B: Texture> {
+    inv_scale: f64,
+    even: A,
+    odd: B,
+}
+
+impl<A: Texture, B: Texture> Checker<A, B> {
+    pub fn new(scale: f64, even: A, odd: B) -> Self {
+        Self {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+}
+
+impl<A: Texture, B: Texture> Texture for Checker<A, B> {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+        let cell = (self.inv_scale * p.x()).floor()
+            + (self.inv_scale * p.y()).floor()
+            + (self.inv_scale * p.z()).floor();
+        if cell.rem_euclid(2.0) == 0.0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+// Marble-like veins from Perlin turbulence.
+pub struct Noise {
+    perlin: Perlin,
+    scale: f64,
+}
+
+impl Noise {
+    pub fn new(scale: f64) -> Self {
+        Self {
+            perlin: Perlin::new(),
+            scale,
+        }
+    }
+}
+
+impl Texture for Noise {
+    fn value(&self, _u: f64, _v: f64, p: Point3) -> Color {
+        let s = 0.5 * (1.0 + (self.scale * p.z() + 10.0 * self.perlin.turb(p, 7)).sin());
+        Color::new(s, s, s)
+    }
+}
+
+// Bilinearly filtered image addressed by (u, v), with v = 0 at the bottom row. Texels are
+// stored linear; `load` decodes files with the same gamma 2 that `write_color` encodes.
+pub struct Image {
+    width: usize,
+    height: usize,
+    texels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, texels: Vec<Color>) -> Self {
+        assert_eq!(texels.len(), width * height);
+        Self {
+            width,
+            height,
+            texels,
+        }
+    }
+
+    // Reads a binary (P6) or ASCII (P3) PPM file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read(path, 2)
+    }
+
+    // Like `load`, for data such as normal maps whose samples are stored without gamma.
+    pub fn load_linear<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read(path, 1)
+    }
+
+    fn read<P: AsRef<Path>>(path: P, gamma: i32) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let mut pos = 0;
+        let magic = next_token(&bytes, &mut pos)?;
+        let width = parse_number(&next_token(&bytes, &mut pos)?)?;
+        let height = parse_number(&next_token(&bytes, &mut pos)?)?;
+        let max_value = parse_number(&next_token(&bytes, &mut pos)?)?;
+        if max_value == 0 || max_value > 65535 {
+            return Err(invalid_data("PPM maximum value out of range"));
+        }
+
+        let count = width
+            .checked_mul(height)
+            .and_then(|n| n.checked_mul(3))
+            .ok_or_else(|| invalid_data("PPM dimensions too large"))?;
+        let samples: Vec<usize> = match magic.as_str() {
+            "P3" => {
+                // Every sample takes at least a separator and a digit.
+                if count > (bytes.len() - pos) / 2 {
+                    return Err(invalid_data("PPM raster is truncated"));
+                }
+                (0..count)
+                    .map(|_| parse_number(&next_token(&bytes, &mut pos)?))
+                    .collect::<io::Result<_>>()?
+            }
+            "P6" => {
+                // A single whitespace byte separates the header from the raster.
+                let raster = bytes.get(pos + 1..).unwrap_or(&[]);
+                let sample_size = if max_value < 256 { 1 } else { 2 };
+                if count
+                    .checked_mul(sample_size)
+                    .is_none_or(|len| raster.len() < len)
+                {
+                    return Err(invalid_data("PPM raster is truncated"));
+                }
+                (0..count)
+                    .map(|i| match sample_size {
+                        1 => raster[i] as usize,
+                        _ => (raster[2 * i] as usize) << 8 | raster[2 * i + 1] as usize,
+                    })
+                    .collect()
+            }
+            _ => return Err(invalid_data("not a P3 or P6 PPM file")),
+        };
+
+        let decode = |s: usize| (s as f64 / max_value as f64).powi(gamma);
+        let texels = samples
+            .chunks_exact(3)
+            .map(|c| Color::new(decode(c[0]), decode(c[1]), decode(c[2])))
+            .collect();
+        Ok(Self::new(width, height, texels))
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let x = x.clamp(0, self.width as i64 - 1) as usize;
+        let y = y.clamp(0, self.height as i64 - 1) as usize;
+        self.texels[y * self.width + x]
+    }
+}
+
+impl Texture for Image {
+    fn value(&self, u: f64, v: f64, _p: Point3) -> Color {
+        if self.texels.is_empty() {
+            return Color::new(0.0, 1.0, 1.0);
+        }
+        // Rows are stored top to bottom. Texel centres sit at half-integer coordinates.
+        let x = u.rem_euclid(1.0) * self.width as f64 - 0.5;
+        let y = (1.0 - v.rem_euclid(1.0)) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (dx, dy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        (1.0 - dx) * (1.0 - dy) * self.texel(x0, y0)
+            + dx * (1.0 - dy) * self.texel(x0 + 1, y0)
+            + (1.0 - dx) * dy * self.texel(x0, y0 + 1)
+            + dx * dy * self.texel(x0 + 1, y0 + 1)
+    }
+}
+
+// Next whitespace separated header token, skipping `#` comments.
+fn next_token(bytes: &[u8], pos: &mut usize) -> io::Result<String> {
+    loop {
+        while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+            *pos += 1;
+        }
+        if *pos < bytes.len() && bytes[*pos] == b'#' {
+            while *pos < bytes.len() && bytes[*pos] != b'\n' {
+                *pos += 1;
+            }
+        } else {
+            break;
+        }
+    }
+    let start = *pos;
+    while *pos < bytes.len() && !bytes[*pos].is_ascii_whitespace() {
+        *pos += 1;
+    }
+    if start == *pos {
+        return Err(invalid_data("unexpected end of PPM file"));
+    }
+    Ok(String::from_utf8_lossy(&bytes[start..*pos]).into_owned())
+}
+
+fn parse_number(token: &str) -> io::Result<usize> {
+    token
+        .parse()
+        .map_err(|_| invalid_data("invalid number in PPM file"))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
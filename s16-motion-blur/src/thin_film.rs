@@ -0,0 +1,172 @@
+use std::{
+    f64::consts::PI,
+    ops::{Add, Div, Mul, Sub},
+    sync::Arc,
+};
+
+use crate::{hittable::HitRecord, texture::Texture, vec3::Color};
+
+// Wavelengths in nm standing in for the red, green and blue channels when rendering in RGB.
+const RGB_WAVELENGTHS: [f64; 3] = [630.0, 532.0, 465.0];
+
+// A thin transparent film on a surface, such as soap or oil, whose reflections interfere.
+// The reflectance of the film and the surface beneath it follows the Airy summation of all
+// internal reflections, averaged over both polarizations. Spectral renders see it at each
+// ray's wavelength; RGB renders at one wavelength per channel, which shows the colours but
+// is less accurate for thick films.
+pub struct ThinFilm {
+    ior: f64,
+    // Thickness in nm.
+    thickness: Arc<dyn Texture>,
+}
+
+impl ThinFilm {
+    // `thickness` is in nanometres, the first channel of a texture for varying films.
+    pub fn new<T: 'static + Texture>(ior: f64, thickness: T) -> Self {
+        Self {
+            ior,
+            thickness: Arc::new(thickness),
+        }
+    }
+
+    // Reflectance at `rec` for light arriving at `cos_theta` to the normal from a medium of
+    // index `n_outside`. `base` gives the complex index (n, k) of what lies under the film at
+    // a wavelength. In spectral mode all channels hold the value at `wavelength`.
+    pub fn reflectance<F: Fn(f64) -> (f64, f64)>(
+        &self,
+        rec: &HitRecord,
+        wavelength: Option<f64>,
+        cos_theta: f64,
+        n_outside: f64,
+        base: F,
+    ) -> Color {
+        let thickness = self.thickness.value(rec.u, rec.v, rec.p).x().max(0.0);
+        let at = |lambda: f64| {
+            let (n, k) = base(lambda);
+            airy(
+                cos_theta,
+                n_outside,
+                self.ior,
+                Complex::new(n, k),
+                thickness,
+                lambda,
+            )
+        };
+        match wavelength {
+            Some(lambda) => {
+                let r = at(lambda);
+                Color::new(r, r, r)
+            }
+            None => Color::new(
+                at(RGB_WAVELENGTHS[0]),
+                at(RGB_WAVELENGTHS[1]),
+                at(RGB_WAVELENGTHS[2]),
+            ),
+        }
+    }
+}
+
+// Reflectance of a film of index `n1` and `thickness` nm between a medium of index `n0`, lit
+// at `cos0`, and a base of complex index `n2`.
+fn airy(cos0: f64, n0: f64, n1: f64, n2: Complex, thickness: f64, lambda: f64) -> f64 {
+    let cos0 = cos0.clamp(0.0, 1.0);
+    let sin2 = 1.0 - cos0 * cos0;
+    let n0 = Complex::real(n0);
+    let n1 = Complex::real(n1);
+    // Snell's law with complex cosines covers absorbing bases and total internal reflection.
+    let cos_in = |n: Complex| (Complex::real(1.0) - n0 * n0 / (n * n) * sin2).sqrt();
+    let (c0, c1, c2) = (Complex::real(cos0), cos_in(n1), cos_in(n2));
+
+    let rs = |na: Complex, ca: Complex, nb: Complex, cb: Complex| {
+        (na * ca - nb * cb) / (na * ca + nb * cb)
+    };
+    let rp = |na: Complex, ca: Complex, nb: Complex, cb: Complex| {
+        (nb * ca - na * cb) / (nb * ca + na * cb)
+    };
+    // Phase picked up by a round trip through the film.
+    let phase = (Complex::real(4.0 * PI * thickness / lambda) * n1 * c1).i_exp();
+
+    let total = |r01: Complex, r12: Complex| {
+        let r12 = r12 * phase;
+        ((r01 + r12) / (Complex::real(1.0) + r01 * r12)).norm_sqr()
+    };
+    let s = total(rs(n0, c0, n1, c1), rs(n1, c1, n2, c2));
+    let p = total(rp(n0, c0, n1, c1), rp(n1, c1, n2, c2));
+    (0.5 * (s + p)).clamp(0.0, 1.0)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn real(re: f64) -> Self {
+        Self::new(re, 0.0)
+    }
+
+    fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // Principal square root.
+    fn sqrt(self) -> Self {
+        let r = self.norm_sqr().sqrt();
+        let re = (0.5 * (r + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (r - self.re)).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    // exp(i * self).
+    fn i_exp(self) -> Self {
+        let scale = (-self.im).exp();
+        Self::new(scale * self.re.cos(), scale * self.re.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, o: Complex) -> Complex {
+        Complex::new(self.re + o.re, self.im + o.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, o: Complex) -> Complex {
+        Complex::new(self.re - o.re, self.im - o.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, o: Complex) -> Complex {
+        Complex::new(
+            self.re * o.re - self.im * o.im,
+            self.re * o.im + self.im * o.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, o: Complex) -> Complex {
+        let d = o.norm_sqr();
+        Complex::new(
+            (self.re * o.re + self.im * o.im) / d,
+            (self.im * o.re - self.re * o.im) / d,
+        )
+    }
+}
+
+impl Mul<f64> for Complex {
+    type Output = Complex;
+    fn mul(self, t: f64) -> Complex {
+        Complex::new(self.re * t, self.im * t)
+    }
+}
@@ -0,0 +1,156 @@
+use std::f64::consts::PI;
+
+use crate::{
+    aabb::Aabb,
+    disk::circle_bounds,
+    hittable::{area_pdf, HitRecord, Shape, SHADOW_EPSILON},
+    onb::Onb,
+    polynomial::solve_quartic,
+    ray::Ray,
+    util::random_f64,
+    vec3::{dot, unit_vector, Point3, Vec3},
+};
+
+// Ring around `axis` through `center`: a tube of `minor_radius` swept along a circle of
+// `major_radius`. u is the angle around the axis, v the angle around the tube starting at its
+// outer edge, both from 0 to 1. The tube may not reach past the axis, so that the torus does
+// not intersect itself.
+pub struct Torus {
+    center: Point3,
+    frame: Onb,
+    major_radius: f64,
+    minor_radius: f64,
+}
+
+impl Torus {
+    pub fn new(center: Point3, axis: Vec3, major_radius: f64, minor_radius: f64) -> Self {
+        assert!(
+            0.0 <= minor_radius && minor_radius <= major_radius,
+            "torus tube radius out of range"
+        );
+        Self {
+            center,
+            frame: Onb::build_right_handed(axis),
+            major_radius,
+            minor_radius,
+        }
+    }
+
+    // Ray parameter and point in the frame of the axis of the nearest intersection with `r`.
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, Point3)> {
+        let o = self.frame.to_local(r.origin() - self.center);
+        let d = self.frame.to_local(r.direction());
+
+        // Solving from where the ray enters the bounding sphere keeps the coefficients of
+        // distant rays from cancelling.
+        let (a, f, g) = (dot(d, d), dot(o, d), dot(o, o));
+        let bound = self.major_radius + self.minor_radius;
+        let discriminant = f * f - a * (g - bound * bound);
+        if discriminant < 0.0 {
+            return None;
+        }
+        let t_enter = (-f - discriminant.sqrt()) / a;
+        let shift = t_enter.max(0.0);
+        let o = o + shift * d;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2)
+        let (f, g) = (dot(o, d), dot(o, o));
+        let r2 = self.major_radius * self.major_radius;
+        let e = g + r2 - self.minor_radius * self.minor_radius;
+        let roots = solve_quartic(
+            e * e - 4.0 * r2 * (g - o.z() * o.z()),
+            4.0 * f * e - 8.0 * r2 * (f - o.z() * d.z()),
+            4.0 * f * f + 2.0 * a * e - 4.0 * r2 * (a - d.z() * d.z()),
+            4.0 * a * f,
+            a * a,
+        );
+        roots
+            .into_iter()
+            .map(|t| t + shift)
+            .find(|&t| t > t_min && t < t_max)
+            .map(|t| (t, self.frame.to_local(r.at(t) - self.center)))
+    }
+
+    // Outward normal at the point `q` in the frame of the axis.
+    fn normal(&self, q: Point3) -> Vec3 {
+        let ring = unit_vector(Vec3::new(q.x(), q.y(), 0.0));
+        self.frame.local(unit_vector(q - self.major_radius * ring))
+    }
+
+    fn point(&self, phi: f64, theta: f64) -> Point3 {
+        let radius = self.major_radius + self.minor_radius * theta.cos();
+        Vec3::new(
+            radius * phi.cos(),
+            radius * phi.sin(),
+            self.minor_radius * theta.sin(),
+        )
+    }
+}
+
+impl Shape for Torus {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (t, q) = match self.intersect(&r, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        rec.t = t;
+        rec.p = r.at(t);
+        rec.set_face_normal(r, self.normal(q));
+
+        let phi = q.y().atan2(q.x()).rem_euclid(2.0 * PI);
+        let ring_distance = (q.x() * q.x() + q.y() * q.y()).sqrt() - self.major_radius;
+        let theta = q.z().atan2(ring_distance).rem_euclid(2.0 * PI);
+        rec.u = phi / (2.0 * PI);
+        rec.v = theta / (2.0 * PI);
+        let (cos_phi, sin_phi) = (phi.cos(), phi.sin());
+        let (cos_theta, sin_theta) = (theta.cos(), theta.sin());
+        rec.dpdu = self.frame.local(2.0 * PI * Vec3::new(-q.y(), q.x(), 0.0));
+        rec.dpdv = self.frame.local(
+            2.0 * PI
+                * self.minor_radius
+                * Vec3::new(-sin_theta * cos_phi, -sin_theta * sin_phi, cos_theta),
+        );
+        true
+    }
+
+    fn occluded(&self, r: &Ray, t_max: f64) -> bool {
+        self.intersect(r, SHADOW_EPSILON, t_max).is_some()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let r = Ray::new(origin, direction);
+        area_pdf(&r, self.area(), |t_min| {
+            let (t, q) = self.intersect(&r, t_min, f64::INFINITY)?;
+            Some((t, self.normal(q)))
+        })
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        self.sample_area().unwrap().0 - origin
+    }
+
+    fn area(&self) -> f64 {
+        4.0 * PI * PI * self.major_radius * self.minor_radius
+    }
+
+    // The area element grows with the distance from the axis, R + r cos(theta), which
+    // rejection sampling of theta follows.
+    fn sample_area(&self) -> Option<(Point3, Vec3)> {
+        let phi = 2.0 * PI * random_f64();
+        let (major, minor) = (self.major_radius, self.minor_radius);
+        let theta = loop {
+            let theta = 2.0 * PI * random_f64();
+            if random_f64() * (major + minor) <= (major + minor * theta.cos()).abs() {
+                break theta;
+            }
+        };
+        let q = self.point(phi, theta);
+        Some((self.center + self.frame.local(q), self.normal(q)))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let ring = circle_bounds(self.center, self.frame.w, self.major_radius);
+        let tube = Vec3::new(self.minor_radius, self.minor_radius, self.minor_radius);
+        Some(Aabb::new(ring.min - tube, ring.max + tube))
+    }
+}
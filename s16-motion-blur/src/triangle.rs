@@ -0,0 +1,149 @@
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Shape, SHADOW_EPSILON},
+    onb::Onb,
+    ray::Ray,
+    util::random_f64,
+    vec3::{cross, dot, unit_vector, Point3, Vec3},
+};
+
+// Triangle with vertices `a`, `b` and `c`, its front face counter-clockwise. Surface
+// coordinates are interpolated from per-vertex ones, by default (0, 0), (1, 0) and (0, 1).
+pub struct Triangle {
+    a: Point3,
+    e1: Vec3,
+    e2: Vec3,
+    normal: Vec3,
+    area: f64,
+    uvs: [(f64, f64); 3],
+}
+
+impl Triangle {
+    pub fn new(a: Point3, b: Point3, c: Point3) -> Self {
+        let (e1, e2) = (b - a, c - a);
+        let n = cross(e1, e2);
+        Self {
+            a,
+            e1,
+            e2,
+            normal: unit_vector(n),
+            area: 0.5 * n.length(),
+            uvs: [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+        }
+    }
+
+    pub fn with_uvs(mut self, uv_a: (f64, f64), uv_b: (f64, f64), uv_c: (f64, f64)) -> Self {
+        self.uvs = [uv_a, uv_b, uv_c];
+        self
+    }
+
+    // Ray parameter and barycentric coordinates of `b` and `c` at the intersection with `r`
+    // in (t_min, t_max) (Moller and Trumbore).
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let p = cross(r.direction(), self.e2);
+        let det = dot(self.e1, p);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let s = r.origin() - self.a;
+        let b1 = dot(s, p) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+        let q = cross(s, self.e1);
+        let b2 = dot(r.direction(), q) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+        let t = dot(self.e2, q) * inv_det;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        Some((t, b1, b2))
+    }
+
+    // Derivatives of the position along the surface coordinates, or any tangent frame where
+    // the coordinates are degenerate.
+    fn tangents(&self) -> (Vec3, Vec3) {
+        let [uv_a, uv_b, uv_c] = self.uvs;
+        let (du_ac, dv_ac) = (uv_a.0 - uv_c.0, uv_a.1 - uv_c.1);
+        let (du_bc, dv_bc) = (uv_b.0 - uv_c.0, uv_b.1 - uv_c.1);
+        let (dp_ac, dp_bc) = (-self.e2, self.e1 - self.e2);
+        let det = du_ac * dv_bc - dv_ac * du_bc;
+        if det.abs() < 1e-12 {
+            let uvw = Onb::build_from_w(self.normal);
+            return (uvw.u, uvw.v);
+        }
+        (
+            (dv_bc * dp_ac - dv_ac * dp_bc) / det,
+            (du_ac * dp_bc - du_bc * dp_ac) / det,
+        )
+    }
+
+    fn point(&self, b1: f64, b2: f64) -> Point3 {
+        self.a + b1 * self.e1 + b2 * self.e2
+    }
+
+    // Uniform barycentric coordinates over the triangle.
+    fn random_barycentric() -> (f64, f64) {
+        let s = random_f64().sqrt();
+        let b2 = random_f64();
+        (s * (1.0 - b2), s * b2)
+    }
+}
+
+impl Shape for Triangle {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        match self.intersect(&r, t_min, t_max) {
+            Some((t, b1, b2)) => {
+                rec.t = t;
+                rec.p = r.at(t);
+                rec.set_face_normal(r, self.normal);
+                let b0 = 1.0 - b1 - b2;
+                let [uv_a, uv_b, uv_c] = self.uvs;
+                rec.u = b0 * uv_a.0 + b1 * uv_b.0 + b2 * uv_c.0;
+                rec.v = b0 * uv_a.1 + b1 * uv_b.1 + b2 * uv_c.1;
+                (rec.dpdu, rec.dpdv) = self.tangents();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn occluded(&self, r: &Ray, t_max: f64) -> bool {
+        self.intersect(r, SHADOW_EPSILON, t_max).is_some()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let r = Ray::new(origin, direction);
+        let t = match self.intersect(&r, SHADOW_EPSILON, f64::INFINITY) {
+            Some((t, _, _)) => t,
+            None => return 0.0,
+        };
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = dot(direction, self.normal).abs() / direction.length();
+        if cosine == 0.0 {
+            return 0.0;
+        }
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        let (b1, b2) = Self::random_barycentric();
+        self.point(b1, b2) - origin
+    }
+
+    fn area(&self) -> f64 {
+        self.area
+    }
+
+    fn sample_area(&self) -> Option<(Point3, Vec3)> {
+        let (b1, b2) = Self::random_barycentric();
+        Some((self.point(b1, b2), self.normal))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.a, self.a + self.e1).union_point(self.a + self.e2))
+    }
+}
@@ -40,6 +40,10 @@ impl Vec3 {
         self.length_squared().sqrt()
     }
 
+    pub fn exp(&self) -> Vec3 {
+        Vec3::new(self.e[0].exp(), self.e[1].exp(), self.e[2].exp())
+    }
+
     pub fn random() -> Vec3 {
         Vec3::new(random_f64(), random_f64(), random_f64())
     }
@@ -68,6 +72,14 @@ impl Vec3 {
         Vec3::new(r * a.cos(), r * a.sin(), z)
     }
 
+    pub fn random_cosine_direction() -> Vec3 {
+        let r1 = random_f64();
+        let r2 = random_f64();
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let z = (1.0 - r2).sqrt();
+        Vec3::new(phi.cos() * r2.sqrt(), phi.sin() * r2.sqrt(), z)
+    }
+
     pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
         let in_unit_sphere = Vec3::random_in_unit_sphere();
         if dot(in_unit_sphere, normal) > 0.0 {